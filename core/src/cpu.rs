@@ -0,0 +1,446 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
+use crate::{DrawOrigin, Keyboard, Memory, Quirks, Screen};
+use crate::profiler::Profiler;
+
+const NUM_REGS: usize = 0x10;
+// Time spent by the VIP in each iteration of a waiting loop, in microseconds
+const VIP_IDLE_US: u32 = 100;
+
+pub struct Cpu {
+  pub v: [u8; NUM_REGS],
+  pub pc: usize,
+  pub i: u16,
+  pub delay_timer: u8,
+  pub sound_timer: u8,
+  // Number of 60Hz ticks since reset
+  pub frame: u64,
+  stack: VecDeque<usize>,
+  waiting_for_key: bool,
+  key_register: usize,
+  // Key pressed during the wait, which must be released to end it
+  key_pressed: Option<u8>,
+  // Whether the 60Hz interrupt ended the wait of the stalled draw
+  vblank: bool,
+  waiting_for_vblank: bool,
+  // The next instruction is a stalled draw, which was already profiled
+  resuming_draw: bool,
+  pub quirks: Quirks,
+
+  rng: StdRng,
+
+  pub profiler: Option<Profiler>,
+}
+
+impl Default for Cpu {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Cpu {
+  pub fn new() -> Self {
+    Self::with_seed(rand::random())
+  }
+
+  // Random numbers come from `seed`, for platforms without a source of entropy
+  pub fn with_seed(seed: u64) -> Self {
+    Self {
+      v: [0; NUM_REGS],
+      pc: 0,
+      i: 0,
+      delay_timer: 0,
+      sound_timer: 0,
+      frame: 0,
+      stack: VecDeque::new(),
+      waiting_for_key: false,
+      key_register: 0,
+      key_pressed: None,
+      vblank: false,
+      waiting_for_vblank: false,
+      resuming_draw: false,
+      quirks: Quirks::default(),
+
+      rng: StdRng::seed_from_u64(seed),
+
+      profiler: None,
+    }
+  }
+}
+
+impl Cpu {
+  // Returns whether the instruction skipped the next one
+  fn exec<M, S, K>(&mut self, opcode: u16, ram: &mut M, screen: &mut S,
+                   keyboard: &mut K) -> bool
+    where M: Memory, S: Screen, K: Keyboard {
+    let addr = opcode & 0x0FFF;
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    let y = ((opcode & 0x00F0) >> 4) as usize;
+    let kk = (opcode & 0x00FF) as u8;
+    let mut skipped = false;
+
+    match opcode & 0xF000 {
+      0x0000 => match opcode & 0x00FF {
+        0x00 => {},
+
+        0xE0 => screen.clear(),
+
+        0xEE => self.pc = self.stack.pop_front().unwrap(),
+
+        _ => panic!("Unknown upcode {:x}", opcode)
+      },
+
+      0x1000 => self.pc = addr as usize,
+
+      0x2000 => {
+        self.stack.push_front(self.pc);
+        self.pc = addr as usize;
+      },
+
+      0x3000 => skipped = self.skip_if(self.v[x] == kk),
+      0x4000 => skipped = self.skip_if(self.v[x] != kk),
+      0x5000 => skipped = self.skip_if(self.v[x] == self.v[y]),
+
+      0x6000 => self.v[x] = kk,
+      0x7000 => self.v[x] = self.v[x].wrapping_add(kk),
+
+      0x8000 => match opcode & 0x000F {
+        0x0 => self.v[x] = self.v[y],
+        0x1 => self.v[x] |= self.v[y],
+        0x2 => self.v[x] &= self.v[y],
+        0x3 => self.v[x] ^= self.v[y],
+
+        0x4 => {
+          let r = (self.v[x] as u16) + (self.v[y] as u16);
+          self.v[0xF] = if r > 0xFF { 1 } else { 0 };
+          self.v[x] = r as u8;
+        },
+        0x5 => {
+          self.v[0xF] = if self.v[x] > self.v[y] { 1 } else { 0 };
+          self.v[x] = self.v[x].wrapping_sub(self.v[y]);
+        },
+
+        0x6 => {
+          self.v[0xF] = self.v[x] & 0x1;
+          self.v[x] >>= 1;
+        },
+
+        0x7 => {
+          self.v[0xF] = if self.v[y] > self.v[x] { 1 } else { 0 };
+          self.v[y] = self.v[y].wrapping_sub(self.v[x]);
+        },
+
+        0xE => {
+          self.v[0xF] = if (self.v[x] & 0x80) > 0 { 1 } else { 0 };
+          self.v[x] <<= 1;
+        },
+
+        _ => panic!("Unknown upcode {:x}", opcode)
+      },
+
+      0x9000 => skipped = self.skip_if(self.v[x] != self.v[y]),
+
+      0xA000 => self.i = addr,
+
+      0xB000 => self.pc = (addr + (self.v[0] as u16)) as usize,
+
+      0xC000 => {
+        let r : u8 = self.rng.gen();
+        self.v[x] = r & kk;
+      },
+
+      0xD000 => {
+        let n = opcode & 0x000F;
+
+        if self.quirks.display_wait && !self.vblank {
+          // Execute this instruction again after the next interrupt
+          self.pc -= 2;
+          self.waiting_for_vblank = true;
+          self.resuming_draw = true;
+          return false
+        }
+        self.vblank = false;
+
+        if let Some(p) = self.profiler.as_mut() {
+          p.draw();
+        }
+
+        // Build sprite
+        let mut sprite = Vec::new();
+
+        for i in (self.i)..(self.i + n) {
+          let p = ram.read(i as usize);
+          for b in (0..8).rev() {
+            sprite.push((p & (1 << b)) >> b);
+          }
+        }
+
+        // Draw
+        screen.trace_draw(DrawOrigin {
+          pc: self.pc - 2,
+          i: self.i,
+          frame: self.frame,
+        });
+        let collision = screen.draw_sprite(self.v[x] as usize,
+                                           self.v[y] as usize,
+                                           &sprite,
+                                           self.quirks.clip_sprites);
+
+        self.v[0xF] = if self.quirks.collision_rows {
+          collision.rows + collision.clipped_rows
+        } else if collision.rows > 0 { 1 } else { 0 };
+      },
+
+      0xE000 => {
+        match opcode & 0x00FF {
+          0x9E => skipped = self.skip_if(keyboard.is_pressed(self.v[x])),
+          0xA1 => skipped = self.skip_if(!keyboard.is_pressed(self.v[x])),
+
+          _ => panic!("Unknown upcode {:x}", opcode)
+        }
+      },
+
+      0xF000 => {
+        match opcode & 0x00FF {
+          0x07 => self.v[x] = self.delay_timer,
+          0x15 => self.delay_timer = self.v[x],
+
+          0x18 => self.sound_timer = self.v[x],
+
+          0x0A => {
+            self.waiting_for_key = true;
+            // Keep track of the register to put the key code in.
+            self.key_register = x;
+            // Only keys pressed from now on count
+            self.key_pressed = None;
+            keyboard.clear_key_presses();
+          },
+
+          0x1E => {
+            let mut r = self.i as u32;
+            r += self.v[x] as u32;
+            self.v[0xF] = if r > 0xFFFF { 1 } else { 0 };
+            self.i = r as u16;
+          },
+
+          0x29 => self.i = self.v[x] as u16 * 5,
+
+          0x33 => {
+            let h = self.v[x] / 100;
+            let d = (self.v[x] % 100) / 10;
+            let u = self.v[x] % 10;
+            let i = self.i as usize;
+            ram.write(i, h);
+            ram.write(i + 1, d);
+            ram.write(i + 2, u);
+          },
+
+          0x55 => {
+            let start = self.i as usize;
+            ram.write_seq(start, &self.v);
+          },
+
+          0x65 => {
+            let si = self.i as usize;
+            for i in 0..(x + 1) {
+              self.v[i] = ram.read(si + i);
+            }
+          },
+
+          _ => panic!("Unknown upcode {:x}", opcode)
+        }
+      }
+
+      _ => panic!("Unknown upcode {:x}", opcode)
+    }
+
+    skipped
+  }
+
+  fn skip_if(&mut self, condition: bool) -> bool {
+    if condition {
+      self.pc += 2;
+    }
+    condition
+  }
+}
+
+impl crate::CPU for Cpu {
+  fn reset(&mut self) {
+    self.pc = 0x200;
+
+    for i in 0..NUM_REGS {
+      self.v[i] = 0;
+    }
+
+    self.i = 0;
+    self.delay_timer = 0;
+    self.sound_timer = 0;
+    self.frame = 0;
+    self.stack.clear();
+    self.waiting_for_key = false;
+    self.key_register = 0;
+    self.key_pressed = None;
+    self.vblank = false;
+    self.waiting_for_vblank = false;
+    self.resuming_draw = false;
+
+    if let Some(p) = self.profiler.as_mut() {
+      p.reset_call_stack();
+    }
+  }
+
+  fn clock<M, S, K>(&mut self, ram: &mut M, screen: &mut S,
+                    keyboard: &mut K) -> u32
+    where M: Memory, S: Screen, K: Keyboard {
+    if self.waiting_for_vblank {
+      return VIP_IDLE_US
+    }
+
+    if self.waiting_for_key {
+      if let Some(p) = self.profiler.as_mut() {
+        p.key_wait();
+      }
+
+      // Wake up once a key has been pressed and released
+      match self.key_pressed {
+        None => self.key_pressed = keyboard.take_key_press(),
+
+        Some(k) => if !keyboard.is_pressed(k) {
+          self.v[self.key_register] = k;
+          self.waiting_for_key = false;
+          self.key_pressed = None;
+        },
+      }
+      return VIP_IDLE_US
+    }
+
+    let opcode = ((ram.fetch(self.pc) as u16) << 8)
+      | (ram.fetch(self.pc + 1) as u16);
+
+    if let Some(p) = self.profiler.as_mut() {
+      if !self.resuming_draw {
+        p.instruction(self.pc, opcode);
+      }
+    }
+    self.resuming_draw = false;
+
+    self.pc += 2;
+
+    let skipped = self.exec(opcode, ram, screen, keyboard);
+
+    if self.waiting_for_vblank {
+      VIP_IDLE_US
+    } else {
+      vip_duration(opcode, skipped)
+    }
+  }
+
+  // This function should be called at 60Hz, regardless of the CPU frequency
+  fn clock_60hz(&mut self) {
+    self.frame += 1;
+    self.vblank = self.waiting_for_vblank;
+    self.waiting_for_vblank = false;
+
+    if let Some(p) = self.profiler.as_mut() {
+      p.frame(self.waiting_for_key);
+    }
+
+    if self.delay_timer > 0 {
+      self.delay_timer -= 1;
+    }
+
+    if self.sound_timer > 0 {
+      self.sound_timer -= 1;
+    }
+  }
+}
+
+// Approximate duration of each instruction on the COSMAC VIP, in microseconds.
+// Drawing does not include waiting for the interrupt.
+fn vip_duration(opcode: u16, skipped: bool) -> u32 {
+  let x = ((opcode & 0x0F00) >> 8) as u32;
+  let n = (opcode & 0x000F) as u32;
+  let skip = if skipped { 9 } else { 0 };
+
+  match opcode & 0xF000 {
+    0x0000 => if opcode == 0x00E0 { 109 } else { 105 },
+    0x1000 | 0x2000 | 0xB000 => 105,
+    0x3000 | 0x4000 => 55 + skip,
+    0x5000 | 0x9000 | 0xE000 => 73 + skip,
+    0x6000 => 27,
+    0x7000 => 45,
+    0x8000 => 200,
+    0xA000 => 55,
+    0xC000 => 164,
+    0xD000 => 170 + 136 * n,
+
+    _ => match opcode & 0x00FF {
+      0x1E => 86,
+      0x29 => 91,
+      0x33 => 927,
+      0x55 | 0x65 => 64 + 64 * (x + 1),
+      _ => 45,
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::CPU;
+  use crate::keyboard::SimpleKeyboard;
+  use crate::memory::RAM;
+  use crate::screen::PixelScreen;
+
+  #[test]
+  fn vip_durations() {
+    assert_eq!(vip_duration(0x00E0, false), 109);
+    assert_eq!(vip_duration(0x3000, false), 55);
+    assert_eq!(vip_duration(0x3000, true), 64);
+    assert_eq!(vip_duration(0xD005, false), 850);
+    assert_eq!(vip_duration(0xF255, false), 256);
+    assert_eq!(vip_duration(0xF033, false), 927);
+  }
+
+  #[test]
+  fn only_skips_take_longer() {
+    let mut cpu = Cpu::new();
+    let mut ram = RAM::new();
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+    cpu.reset();
+    ram.write_seq(0x200, &[
+      0x12, 0x04, // JP 204
+      0x00, 0x00,
+      0x30, 0x00, // SE V0, 0
+    ]);
+
+    // A jump two instructions ahead is not a skip
+    assert_eq!(cpu.clock(&mut ram, &mut screen, &mut keyboard), 105);
+    assert_eq!(cpu.clock(&mut ram, &mut screen, &mut keyboard), 64);
+    assert_eq!(cpu.pc, 0x208);
+  }
+
+  #[test]
+  fn stalled_draws_are_profiled_once() {
+    let mut cpu = Cpu::new();
+    let mut ram = RAM::new();
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+    cpu.quirks.display_wait = true;
+    cpu.profiler = Some(Profiler::new());
+    cpu.reset();
+    ram.write_seq(0x200, &[0xD0, 0x05]);
+
+    // Stalls until the interrupt, then draws
+    assert_eq!(cpu.clock(&mut ram, &mut screen, &mut keyboard), VIP_IDLE_US);
+    cpu.clock(&mut ram, &mut screen, &mut keyboard);
+    cpu.clock_60hz();
+    assert_eq!(cpu.clock(&mut ram, &mut screen, &mut keyboard), 850);
+    assert_eq!(cpu.pc, 0x202);
+
+    let p = cpu.profiler.as_ref().unwrap();
+    assert_eq!(p.hot_spots()[0], (0x200, 1));
+  }
+}
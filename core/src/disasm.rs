@@ -0,0 +1,93 @@
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Disassembler using Cowgod's mnemonics
+
+pub fn disassemble(opcode: u16) -> String {
+  let addr = opcode & 0x0FFF;
+  let x = (opcode & 0x0F00) >> 8;
+  let y = (opcode & 0x00F0) >> 4;
+  let n = opcode & 0x000F;
+  let kk = opcode & 0x00FF;
+
+  match opcode & 0xF000 {
+    0x0000 => match opcode {
+      0x00E0 => "CLS".to_string(),
+      0x00EE => "RET".to_string(),
+      _ => format!("SYS {:03x}", addr),
+    },
+
+    0x1000 => format!("JP {:03x}", addr),
+    0x2000 => format!("CALL {:03x}", addr),
+    0x3000 => format!("SE V{:X}, {:02x}", x, kk),
+    0x4000 => format!("SNE V{:X}, {:02x}", x, kk),
+    0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+    0x6000 => format!("LD V{:X}, {:02x}", x, kk),
+    0x7000 => format!("ADD V{:X}, {:02x}", x, kk),
+
+    0x8000 => match n {
+      0x0 => format!("LD V{:X}, V{:X}", x, y),
+      0x1 => format!("OR V{:X}, V{:X}", x, y),
+      0x2 => format!("AND V{:X}, V{:X}", x, y),
+      0x3 => format!("XOR V{:X}, V{:X}", x, y),
+      0x4 => format!("ADD V{:X}, V{:X}", x, y),
+      0x5 => format!("SUB V{:X}, V{:X}", x, y),
+      0x6 => format!("SHR V{:X}", x),
+      0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+      0xE => format!("SHL V{:X}", x),
+      _ => data(opcode),
+    },
+
+    0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+    0xA000 => format!("LD I, {:03x}", addr),
+    0xB000 => format!("JP V0, {:03x}", addr),
+    0xC000 => format!("RND V{:X}, {:02x}", x, kk),
+    0xD000 => format!("DRW V{:X}, V{:X}, {:x}", x, y, n),
+
+    0xE000 => match kk {
+      0x9E => format!("SKP V{:X}", x),
+      0xA1 => format!("SKNP V{:X}", x),
+      _ => data(opcode),
+    },
+
+    0xF000 => match kk {
+      0x07 => format!("LD V{:X}, DT", x),
+      0x0A => format!("LD V{:X}, K", x),
+      0x15 => format!("LD DT, V{:X}", x),
+      0x18 => format!("LD ST, V{:X}", x),
+      0x1E => format!("ADD I, V{:X}", x),
+      0x29 => format!("LD F, V{:X}", x),
+      0x33 => format!("LD B, V{:X}", x),
+      0x55 => format!("LD [I], V{:X}", x),
+      0x65 => format!("LD V{:X}, [I]", x),
+      _ => data(opcode),
+    },
+
+    _ => data(opcode),
+  }
+}
+
+fn data(opcode: u16) -> String {
+  format!("DW {:04x}", opcode)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn disassembles_instructions() {
+    assert_eq!(disassemble(0x00E0), "CLS");
+    assert_eq!(disassemble(0x2345), "CALL 345");
+    assert_eq!(disassemble(0x5AB0), "SE VA, VB");
+    assert_eq!(disassemble(0x8126), "SHR V1");
+    assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+    assert_eq!(disassemble(0xF355), "LD [I], V3");
+  }
+
+  #[test]
+  fn shows_unknown_opcodes_as_data() {
+    assert_eq!(disassemble(0x5AB1), "DW 5ab1");
+    assert_eq!(disassemble(0x812F), "DW 812f");
+    assert_eq!(disassemble(0xE0FF), "DW e0ff");
+    assert_eq!(disassemble(0xF0FF), "DW f0ff");
+  }
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+const NUM_KEYS: usize = 0x10;
+
+// Inputs that hold keys down.  A key stays down until all the inputs holding
+// it let go.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeySource {
+  Keyboard,
+  Gamepad,
+  Keypad,
+}
+
+pub struct SimpleKeyboard {
+  // Sources holding each key, one bit per source
+  pressed_keys: [u8; NUM_KEYS],
+  // Keys that went down, oldest first
+  presses: VecDeque<u8>,
+}
+
+impl Default for SimpleKeyboard {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl SimpleKeyboard {
+  pub fn new() -> Self {
+    Self {
+      pressed_keys: [0; NUM_KEYS],
+      presses: VecDeque::with_capacity(NUM_KEYS),
+    }
+  }
+
+  pub fn press_key(&mut self, key: u8) {
+    self.press_key_from(KeySource::Keyboard, key)
+  }
+
+  pub fn release_key(&mut self, key: u8) {
+    self.release_key_from(KeySource::Keyboard, key)
+  }
+
+  pub fn press_key_from(&mut self, source: KeySource, key: u8) {
+    // Ignore key repeats, and keys another source already holds
+    if self.pressed_keys[key as usize] == 0 {
+      if self.presses.len() == NUM_KEYS {
+        self.presses.pop_front();
+      }
+      self.presses.push_back(key);
+    }
+    self.pressed_keys[key as usize] |= 1 << source as u8
+  }
+
+  pub fn release_key_from(&mut self, source: KeySource, key: u8) {
+    self.pressed_keys[key as usize] &= !(1 << source as u8)
+  }
+}
+
+impl crate::Keyboard for SimpleKeyboard {
+  fn is_pressed(&self, key: u8) -> bool {
+    self.pressed_keys[key as usize] != 0
+  }
+
+  fn take_key_press(&mut self) -> Option<u8> {
+    self.presses.pop_front()
+  }
+
+  fn clear_key_presses(&mut self) {
+    self.presses.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Keyboard;
+
+  #[test]
+  fn keys_stay_down_while_a_source_holds_them() {
+    let mut keyboard = SimpleKeyboard::new();
+    keyboard.press_key(0x5);
+    keyboard.press_key_from(KeySource::Gamepad, 0x5);
+    keyboard.release_key_from(KeySource::Gamepad, 0x5);
+    assert!(keyboard.is_pressed(0x5));
+    keyboard.release_key(0x5);
+    assert!(!keyboard.is_pressed(0x5));
+
+    // A single press for both sources
+    assert_eq!(keyboard.take_key_press(), Some(0x5));
+    assert_eq!(keyboard.take_key_press(), None);
+  }
+}
@@ -0,0 +1,427 @@
+pub mod cpu;
+pub mod disasm;
+pub mod memory;
+pub mod screen;
+pub mod keyboard;
+pub mod profiler;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Traits used as interfaces for plugging different components into the machine
+
+pub trait CPU {
+  fn reset(&mut self);
+  // Returns how long the instruction would have taken on the COSMAC VIP, in
+  // microseconds
+  fn clock<M, S, K>(&mut self, mem: &mut M, screen: &mut S,
+                   keyboard: &mut K) -> u32
+    where M: Memory, S: Screen, K: Keyboard;
+  fn clock_60hz(&mut self);
+}
+
+pub trait Memory {
+  fn reset(&mut self);
+  fn read(&mut self, addr: usize) -> u8;
+  // Read an opcode byte, as opposed to data
+  fn fetch(&mut self, addr: usize) -> u8 {
+    self.read(addr)
+  }
+  fn write(&mut self, addr: usize, v: u8);
+  fn write_seq(&mut self, start: usize, bytes: &[u8]);
+}
+
+pub trait Screen {
+  fn clear(&mut self);
+  // Sprites wrap around the screen edges, or are clipped if `clip` is set.
+  // Only the starting coordinates wrap when clipping.
+  fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8],
+                 clip: bool) -> Collision;
+  // Called before draw_sprite with the instruction responsible for the draw
+  fn trace_draw(&mut self, _origin: DrawOrigin) {}
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Collision {
+  // Sprite rows that turned off at least one pixel
+  pub rows: u8,
+  // Sprite rows that fell off the bottom of the screen
+  pub clipped_rows: u8,
+}
+
+// The Dxyn instruction behind a sprite draw, for debugging
+#[derive(Clone, Copy, Default)]
+pub struct DrawOrigin {
+  pub pc: usize,
+  pub i: u16,
+  pub frame: u64,
+}
+
+pub trait Keyboard {
+  fn is_pressed(&self, key: u8) -> bool;
+  // Oldest key press that has not been taken yet
+  fn take_key_press(&mut self) -> Option<u8>;
+  fn clear_key_presses(&mut self);
+}
+
+
+// Behaviors that differ between interpreters
+#[derive(Clone, Copy, Default)]
+pub struct Quirks {
+  // Dxyn waits for the next 60Hz interrupt before drawing
+  pub display_wait: bool,
+  // Sprites are clipped at the screen edges instead of wrapping around
+  pub clip_sprites: bool,
+  // VF is set to the number of rows that collided or were clipped, as SCHIP
+  // does in hi-res mode, instead of 1 on any collision
+  pub collision_rows: bool,
+}
+
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Top-level machine
+
+const DEFAULT_FREQUENCY: u64 = 600;
+pub const PERIOD_60HZ: f32 = 1000.0 / 60.0;
+// Where programs are loaded, after the memory of the original interpreter
+pub const ROM_START: usize = 0x200;
+// Time left to the interpreter between two interrupts on the COSMAC VIP, in
+// microseconds.  The rest of the frame goes to the display DMA and the
+// interrupt routine.
+const VIP_ACTIVE_US: i64 = 11_800;
+
+// How many instructions the CPU executes for a given amount of time
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timing {
+  // Execute `freq` instructions per second of elapsed time
+  RealTime,
+  // Execute exactly that many instructions per 60Hz tick, as Octo does.
+  // Emulation does not depend on the host frame rate.
+  PerFrame(u32),
+  // Each instruction takes as long as it did on the COSMAC VIP, and timers
+  // are decremented by the display interrupt
+  Vip,
+}
+
+pub struct Chip8<C: CPU, M: Memory> {
+  pub freq: u64,
+  pub timing: Timing,
+  cycles: f64,
+  counter_60hz: f32,
+  vip_budget: i64,
+  pub cpu: C,
+  pub ram: M,
+}
+
+impl<C: CPU, M: Memory> Chip8<C, M> {
+  pub fn new(cpu: C, ram: M) -> Self {
+    Self {
+      freq: DEFAULT_FREQUENCY,
+      timing: Timing::RealTime,
+      cycles: 0.0,
+      counter_60hz: 0.0,
+      vip_budget: 0,
+      cpu,
+      ram,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.cycles = 0.0;
+    self.counter_60hz = 0.0;
+    self.vip_budget = 0;
+
+    self.cpu.reset();
+    self.ram.reset();
+
+    self.load_font();
+  }
+
+  fn load_font(&mut self) {
+    let font = [
+      0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
+      0x20, 0x60, 0x20, 0x20, 0x70, // 1
+      0xf0, 0x10, 0xf0, 0x80, 0xf0, // 2
+      0xf0, 0x10, 0xf0, 0x10, 0xf0, // 3
+      0x90, 0x90, 0xf0, 0x10, 0x10, // 4
+      0xf0, 0x80, 0xf0, 0x10, 0xf0, // 5
+      0xf0, 0x80, 0xf0, 0x90, 0xf0, // 6
+      0xf0, 0x10, 0x20, 0x40, 0x40, // 7
+      0xf0, 0x90, 0xf0, 0x90, 0xf0, // 8
+      0xf0, 0x90, 0xf0, 0x10, 0xf0, // 9
+      0xf0, 0x90, 0xf0, 0x90, 0x90, // A
+      0xe0, 0x90, 0xe0, 0x90, 0xe0, // B
+      0xf0, 0x80, 0x80, 0x80, 0xf0, // C
+      0xe0, 0x90, 0x90, 0x90, 0xe0, // D
+      0xf0, 0x80, 0xf0, 0x80, 0xf0, // E
+      0xf0, 0x80, 0xf0, 0x80, 0x80  // F
+    ];
+    self.ram.write_seq(0, &font);
+  }
+
+  pub fn load_rom(&mut self, rom: &[u8]) {
+    self.ram.write_seq(ROM_START, &rom);
+  }
+
+  fn run_cycles<S, K>(&mut self, ms: f32, screen: &mut S,
+                      keyboard: &mut K) where S: Screen, K: Keyboard {
+    self.cycles += (ms * (self.freq as f32) / 1000.0) as f64;
+
+    while self.cycles > 0.0 {
+      self.cpu.clock(&mut self.ram, screen, keyboard);
+      self.cycles -= 1.0;
+    }
+  }
+
+  pub fn run<S, K>(&mut self, ms: f32, screen: &mut S,
+                   keyboard: &mut K) where S: Screen, K: Keyboard {
+    match self.timing {
+      Timing::RealTime => {
+        // Stop at each 60Hz tick, so that a CPU waiting on it wakes up in time.
+        // As in the other modes, a run reaching the tick exactly includes it.
+        let mut ms = ms;
+        while self.counter_60hz + ms >= PERIOD_60HZ {
+          let until_tick = PERIOD_60HZ - self.counter_60hz;
+          self.run_cycles(until_tick, screen, keyboard);
+          self.cpu.clock_60hz();
+          self.counter_60hz = 0.0;
+          ms -= until_tick;
+        }
+
+        self.run_cycles(ms, screen, keyboard);
+        self.counter_60hz += ms;
+      },
+
+      Timing::PerFrame(ipf) => {
+        self.counter_60hz += ms;
+        while self.counter_60hz >= PERIOD_60HZ {
+          for _ in 0..ipf {
+            self.cpu.clock(&mut self.ram, screen, keyboard);
+          }
+          self.cpu.clock_60hz();
+          self.counter_60hz -= PERIOD_60HZ;
+        }
+      },
+
+      Timing::Vip => {
+        self.counter_60hz += ms;
+        while self.counter_60hz >= PERIOD_60HZ {
+          // An instruction running over the interrupt eats into the next frame
+          self.vip_budget += VIP_ACTIVE_US;
+          while self.vip_budget > 0 {
+            self.vip_budget -=
+              self.cpu.clock(&mut self.ram, screen, keyboard) as i64;
+          }
+          self.cpu.clock_60hz();
+          self.counter_60hz -= PERIOD_60HZ;
+        }
+      },
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::cpu::Cpu;
+  use super::keyboard::SimpleKeyboard;
+  use super::memory::RAM;
+  use super::screen::PixelScreen;
+
+  // Draw the 0 glyph twice in a row, then loop
+  const DOUBLE_DRAW: [u8; 10] = [
+    0xA0, 0x00, // LD I, 0
+    0x60, 0x00, // LD V0, 0
+    0xD0, 0x05, // DRW V0, V0, 5
+    0xD0, 0x05, // DRW V0, V0, 5
+    0x12, 0x08, // JP 208
+  ];
+
+  // Count to 10, then draw and loop
+  const LATE_DRAW: [u8; 14] = [
+    0xA0, 0x00, // LD I, 0
+    0x60, 0x00, // LD V0, 0
+    0x70, 0x01, // ADD V0, 1
+    0x30, 0x0A, // SE V0, 10
+    0x12, 0x04, // JP 204
+    0xD0, 0x05, // DRW V0, V0, 5
+    0x12, 0x0C, // JP 20C
+  ];
+
+  fn machine(display_wait: bool, timing: Timing) -> Chip8<Cpu, RAM> {
+    let mut chip8 = Chip8::new(Cpu::new(), RAM::new());
+    chip8.reset();
+    chip8.load_rom(&DOUBLE_DRAW);
+    chip8.timing = timing;
+    chip8.cpu.quirks.display_wait = display_wait;
+    chip8
+  }
+
+  fn drawn(screen: &PixelScreen) -> bool {
+    screen.pixels()[0] == 1
+  }
+
+  // Wait for a key, then set V2 and loop
+  const KEY_WAIT: [u8; 10] = [
+    0x60, 0x05, // LD V0, 5
+    0xF0, 0x15, // LD DT, V0
+    0xF1, 0x0A, // LD V1, K
+    0x62, 0x01, // LD V2, 1
+    0x12, 0x08, // JP 208
+  ];
+
+  #[test]
+  fn key_wait_needs_press_and_release() {
+    let mut chip8 = Chip8::new(Cpu::new(), RAM::new());
+    chip8.reset();
+    chip8.load_rom(&KEY_WAIT);
+    chip8.timing = Timing::PerFrame(10);
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    // A key held before the wait does not count
+    keyboard.press_key(0x2);
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.v[2], 0);
+
+    // Nor does a key that is still down
+    keyboard.press_key(0x7);
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.v[2], 0);
+
+    keyboard.release_key(0x7);
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.v[1], 0x7);
+    assert_eq!(chip8.cpu.v[2], 1);
+
+    // Timers kept running during the wait
+    assert_eq!(chip8.cpu.delay_timer, 2);
+  }
+
+  #[test]
+  fn key_wait_sees_quick_presses() {
+    let mut chip8 = Chip8::new(Cpu::new(), RAM::new());
+    chip8.reset();
+    chip8.load_rom(&KEY_WAIT);
+    chip8.timing = Timing::PerFrame(10);
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    keyboard.press_key(0xB);
+    keyboard.release_key(0xB);
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.v[1], 0xB);
+    assert_eq!(chip8.cpu.v[2], 1);
+  }
+
+  // Count in V0 forever
+  const COUNT: [u8; 4] = [
+    0x70, 0x01, // ADD V0, 1
+    0x12, 0x00, // JP 200
+  ];
+
+  #[test]
+  fn per_frame_timing_ignores_host_rate() {
+    let run = |calls: usize, ms: f32| {
+      let mut chip8 = Chip8::new(Cpu::new(), RAM::new());
+      chip8.reset();
+      chip8.load_rom(&COUNT);
+      chip8.timing = Timing::PerFrame(10);
+      chip8.cpu.delay_timer = 100;
+      let mut screen = PixelScreen::new();
+      let mut keyboard = SimpleKeyboard::new();
+      for _ in 0..calls {
+        chip8.run(ms, &mut screen, &mut keyboard);
+      }
+      (chip8.cpu.v[0], chip8.cpu.pc, chip8.cpu.delay_timer)
+    };
+
+    // 4 frames at 60, 30 and 120Hz
+    let expected = (20, 0x200, 96);
+    assert_eq!(run(4, PERIOD_60HZ), expected);
+    assert_eq!(run(2, PERIOD_60HZ * 2.0), expected);
+    assert_eq!(run(8, PERIOD_60HZ / 2.0), expected);
+  }
+
+  #[test]
+  fn vip_timing_counts_cycles() {
+    let mut chip8 = Chip8::new(Cpu::new(), RAM::new());
+    chip8.reset();
+    chip8.load_rom(&COUNT);
+    chip8.timing = Timing::Vip;
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    // Each loop takes 45 + 105us out of the 11800us of a frame.  The last
+    // one runs 50us over, which the next frame makes up for.
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.v[0], 79);
+    assert_eq!(chip8.vip_budget, -50);
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.v[0], 158);
+    assert_eq!(chip8.vip_budget, -100);
+  }
+
+  #[test]
+  fn draws_without_waiting() {
+    let mut chip8 = machine(false, Timing::PerFrame(10));
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    // Both draws happen in the first frame, and cancel each other
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert!(!drawn(&screen));
+  }
+
+  #[test]
+  fn display_wait_allows_one_draw_per_frame() {
+    let mut chip8 = machine(true, Timing::PerFrame(10));
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    // The first draw waits for the first interrupt
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert!(!drawn(&screen));
+    assert_eq!(chip8.cpu.pc, 0x204);
+
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert!(drawn(&screen));
+    assert_eq!(chip8.cpu.pc, 0x206);
+
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert!(!drawn(&screen));
+    assert_eq!(chip8.cpu.pc, 0x208);
+  }
+
+  #[test]
+  fn display_wait_always_waits_for_the_next_interrupt() {
+    let mut chip8 = machine(true, Timing::PerFrame(10));
+    chip8.load_rom(&LATE_DRAW);
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    // The draw comes in the middle of the fourth frame, after interrupts
+    // without draws, and still waits for the end of the frame
+    for _ in 0..4 {
+      chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    }
+    assert_eq!(chip8.cpu.pc, 0x20A);
+    assert!(screen.pixels().iter().all(|&p| p == 0));
+
+    chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+    assert_eq!(chip8.cpu.pc, 0x20C);
+    assert!(screen.pixels().contains(&1));
+  }
+
+  #[test]
+  fn display_wait_wakes_up_within_a_long_run() {
+    let mut chip8 = machine(true, Timing::RealTime);
+    let mut screen = PixelScreen::new();
+    let mut keyboard = SimpleKeyboard::new();
+
+    // A single call spanning an interrupt draws once after it
+    chip8.run(PERIOD_60HZ * 1.5, &mut screen, &mut keyboard);
+    assert!(drawn(&screen));
+    assert_eq!(chip8.cpu.pc, 0x206);
+  }
+}
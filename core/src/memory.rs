@@ -0,0 +1,226 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::disasm::disassemble;
+
+pub const RAM_LENGTH: usize = 0x1000;
+
+// Flags for the coverage map of WatchedRAM
+pub const EXECUTED: u8 = 0x1;
+pub const READ: u8 = 0x2;
+pub const WRITTEN: u8 = 0x4;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// A straightforward RAM array
+
+pub struct RAM {
+  mem: [u8; RAM_LENGTH],
+}
+
+impl Default for RAM {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl RAM {
+  pub fn new() -> Self {
+    Self {
+      mem: [0; RAM_LENGTH],
+    }
+  }
+
+  pub fn read_all(&self) -> &[u8] {
+    &self.mem[..]
+  }
+}
+
+impl crate::Memory for RAM {
+  fn reset(&mut self) {
+    for c in self.mem.iter_mut() {
+      *c = 0;
+    }
+  }
+
+  fn read(&mut self, addr: usize) -> u8 {
+    self.mem[addr]
+  }
+
+
+  fn write(&mut self, addr: usize, v: u8) {
+    self.mem[addr] = v;
+  }
+
+  fn write_seq(&mut self, start: usize, bytes: &[u8]) {
+    self.mem[start..(start + bytes.len())].copy_from_slice(bytes);
+  }
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// A RAM that keeps track of reads and writes for each address, useful for
+// debugging.  The coverage map accumulates how each address was accessed since
+// the last call to reset_coverage.
+
+pub struct WatchedRAM {
+  ram: RAM,
+  pub reads: [u64; RAM_LENGTH],
+  pub writes: [u64; RAM_LENGTH],
+  pub coverage: [u8; RAM_LENGTH],
+}
+
+impl Default for WatchedRAM {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl WatchedRAM {
+  pub fn new() -> Self {
+    Self {
+      ram: RAM::new(),
+      reads: [0; RAM_LENGTH],
+      writes: [0; RAM_LENGTH],
+      coverage: [0; RAM_LENGTH],
+    }
+  }
+
+  pub fn reset_reads_writes(&mut self) {
+    for c in self.reads.iter_mut() {
+      *c = 0;
+    }
+    for c in self.writes.iter_mut() {
+      *c = 0;
+    }
+  }
+
+  pub fn reset_coverage(&mut self) {
+    for c in self.coverage.iter_mut() {
+      *c = 0;
+    }
+  }
+
+  pub fn read_all(&self) -> &[u8] {
+    // Don't keep track of reads here since the CPU cannot access it
+    self.ram.read_all()
+  }
+
+  // Annotated disassembly of the given range.  Executed addresses are shown
+  // as instructions, everything else as data bytes.
+  pub fn write_coverage_report<W: Write>(&self, out: &mut W,
+                                         range: Range<usize>) -> io::Result<()> {
+    let mem = self.ram.read_all();
+    let count = |flag| self.coverage[range.clone()].iter()
+      .filter(|&&c| c & flag != 0)
+      .count();
+    let untouched = self.coverage[range.clone()].iter()
+      .filter(|&&c| c == 0)
+      .count();
+
+    // Both ends of the range are shown inclusive
+    writeln!(out, "range: {:03x}-{:03x} ({} bytes)",
+             range.start, range.end.saturating_sub(1), range.len())?;
+    writeln!(out, "executed: {} bytes", count(EXECUTED))?;
+    writeln!(out, "read as data: {} bytes", count(READ))?;
+    writeln!(out, "written: {} bytes", count(WRITTEN))?;
+    writeln!(out, "never reached: {} bytes", untouched)?;
+    writeln!(out)?;
+
+    let mut a = range.start;
+    while a < range.end {
+      let c = self.coverage[a];
+      let flags = format!("{}{}{}",
+                          if c & EXECUTED != 0 { 'X' } else { '-' },
+                          if c & READ != 0 { 'R' } else { '-' },
+                          if c & WRITTEN != 0 { 'W' } else { '-' });
+
+      if c & EXECUTED != 0 && a + 1 < range.end {
+        let opcode = ((mem[a] as u16) << 8) | (mem[a + 1] as u16);
+        writeln!(out, "{:03x}: {:04x} {} {}",
+                 a, opcode, flags, disassemble(opcode))?;
+        a += 2;
+      } else {
+        writeln!(out, "{:03x}: {:02x}   {} DB {:02x}", a, mem[a], flags, mem[a])?;
+        a += 1;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl crate::Memory for WatchedRAM {
+  fn reset(&mut self) {
+    self.ram.reset();
+  }
+
+  fn read(&mut self, addr: usize) -> u8 {
+    self.reads[addr] += 1;
+    self.coverage[addr] |= READ;
+    self.ram.read(addr)
+  }
+
+  fn fetch(&mut self, addr: usize) -> u8 {
+    self.reads[addr] += 1;
+    self.coverage[addr] |= EXECUTED;
+    self.ram.read(addr)
+  }
+
+  fn write(&mut self, addr: usize, v: u8) {
+    self.writes[addr] += 1;
+    self.coverage[addr] |= WRITTEN;
+    self.ram.write(addr, v);
+  }
+
+  fn write_seq(&mut self, start: usize, bytes: &[u8]) {
+    self.ram.write_seq(start, bytes);
+    for addr in start..(start + bytes.len()) {
+      self.writes[addr] += 1;
+      self.coverage[addr] |= WRITTEN;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Memory;
+
+  fn report(ram: &WatchedRAM, range: Range<usize>) -> String {
+    let mut out = Vec::new();
+    ram.write_coverage_report(&mut out, range).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn reports_coverage() {
+    let mut ram = WatchedRAM::new();
+    ram.write_seq(0x200, &[0x00, 0xE0, 0x12, 0x00, 0x42]);
+    ram.reset_coverage();
+    for a in 0x200..0x204 {
+      ram.fetch(a);
+    }
+    ram.read(0x204);
+
+    let lines: Vec<String> = report(&ram, 0x200..0x206)
+      .lines().map(str::to_string).collect();
+    assert_eq!(lines[0], "range: 200-205 (6 bytes)");
+    assert_eq!(lines[1], "executed: 4 bytes");
+    assert_eq!(lines[2], "read as data: 1 bytes");
+    assert_eq!(lines[4], "never reached: 1 bytes");
+    assert_eq!(&lines[6..], ["200: 00e0 X-- CLS",
+                             "202: 1200 X-- JP 200",
+                             "204: 42   -R- DB 42",
+                             "205: 00   --- DB 00"]);
+  }
+
+  #[test]
+  fn reports_instructions_within_the_range() {
+    let mut ram = WatchedRAM::new();
+    ram.write_seq(0x200, &[0x00, 0xE0]);
+    ram.fetch(0x200);
+
+    // The second byte of the instruction is past the end of the range
+    let report = report(&ram, 0x200..0x201);
+    assert!(report.ends_with("200: 00   X-W DB 00\n"), "{}", report);
+  }
+}
@@ -0,0 +1,244 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+
+use crate::memory::RAM_LENGTH;
+
+const DRAW_HISTORY_LENGTH: usize = 128;
+const MAIN_ENTRY: usize = 0x200;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Execution profiler, fed by the CPU as it executes instructions
+
+#[derive(Clone, Default)]
+pub struct Subroutine {
+  pub calls: u64,
+  // Instructions executed in the subroutine itself
+  pub self_instructions: u64,
+  // Instructions executed in the subroutine and everything it called
+  pub total_instructions: u64,
+}
+
+pub struct Profiler {
+  pub instructions: Vec<u64>,
+  pub subroutines: HashMap<usize, Subroutine>,
+  pub total_instructions: u64,
+  pub key_wait_cycles: u64,
+  pub key_wait_frames: u64,
+  pub frames: u64,
+  pub total_draws: u64,
+  pub max_draws_per_frame: u64,
+  pub draw_history: VecDeque<u64>,
+  draws_this_frame: u64,
+  // Entry address of each active subroutine, with the instruction count at
+  // the time of the call
+  call_stack: Vec<(usize, u64)>,
+}
+
+impl Default for Profiler {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Profiler {
+  pub fn new() -> Self {
+    let mut subroutines = HashMap::new();
+    subroutines.insert(MAIN_ENTRY, Subroutine::default());
+
+    Self {
+      instructions: vec![0; RAM_LENGTH],
+      subroutines,
+      total_instructions: 0,
+      key_wait_cycles: 0,
+      key_wait_frames: 0,
+      frames: 0,
+      total_draws: 0,
+      max_draws_per_frame: 0,
+      draw_history: VecDeque::with_capacity(DRAW_HISTORY_LENGTH),
+      draws_this_frame: 0,
+      call_stack: vec![(MAIN_ENTRY, 0)],
+    }
+  }
+
+  // Forget the call structure when the machine is reset, but keep the counts
+  pub fn reset_call_stack(&mut self) {
+    self.call_stack.clear();
+    self.call_stack.push((MAIN_ENTRY, self.total_instructions));
+  }
+
+  pub fn instruction(&mut self, pc: usize, opcode: u16) {
+    self.instructions[pc] += 1;
+    self.total_instructions += 1;
+
+    let current = self.call_stack.last().map_or(MAIN_ENTRY, |&(a, _)| a);
+    self.subroutines.entry(current).or_default().self_instructions += 1;
+
+    if opcode & 0xF000 == 0x2000 {
+      let addr = (opcode & 0x0FFF) as usize;
+      self.subroutines.entry(addr).or_default().calls += 1;
+      self.call_stack.push((addr, self.total_instructions));
+    }
+    else if opcode == 0x00EE && self.call_stack.len() > 1 {
+      let (addr, start) = self.call_stack.pop().unwrap();
+      // A recursive call is already counted by the outermost one
+      if !self.call_stack.iter().any(|&(a, _)| a == addr) {
+        self.subroutines.entry(addr).or_default().total_instructions +=
+          self.total_instructions - start;
+      }
+    }
+  }
+
+  pub fn key_wait(&mut self) {
+    self.key_wait_cycles += 1;
+  }
+
+  pub fn draw(&mut self) {
+    self.draws_this_frame += 1;
+    self.total_draws += 1;
+  }
+
+  pub fn frame(&mut self, waiting_for_key: bool) {
+    self.frames += 1;
+    if waiting_for_key {
+      self.key_wait_frames += 1;
+    }
+
+    if self.draws_this_frame > self.max_draws_per_frame {
+      self.max_draws_per_frame = self.draws_this_frame;
+    }
+    if self.draw_history.len() == DRAW_HISTORY_LENGTH {
+      self.draw_history.pop_front();
+    }
+    self.draw_history.push_back(self.draws_this_frame);
+    self.draws_this_frame = 0;
+  }
+
+  // Addresses sorted by decreasing execution count
+  pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+    let mut spots: Vec<(usize, u64)> = self.instructions.iter().cloned()
+      .enumerate()
+      .filter(|&(_, n)| n > 0)
+      .collect();
+    spots.sort_by_key(|&(_, n)| Reverse(n));
+    spots
+  }
+
+  // Subroutines sorted by decreasing inclusive instruction count.  Calls that
+  // have not returned yet are counted up to now.
+  pub fn hot_subroutines(&self) -> Vec<(usize, Subroutine)> {
+    let mut subs: HashMap<usize, Subroutine> = self.subroutines.clone();
+    for (i, &(addr, start)) in self.call_stack.iter().enumerate() {
+      if !self.call_stack[..i].iter().any(|&(a, _)| a == addr) {
+        subs.entry(addr).or_default().total_instructions +=
+          self.total_instructions - start;
+      }
+    }
+
+    let mut subs: Vec<(usize, Subroutine)> = subs.into_iter().collect();
+    subs.sort_by(|a, b| b.1.total_instructions.cmp(&a.1.total_instructions)
+                 .then(b.1.self_instructions.cmp(&a.1.self_instructions)));
+    subs
+  }
+
+  fn percent(&self, n: u64) -> f64 {
+    if self.total_instructions == 0 { 0.0 }
+    else { 100.0 * n as f64 / self.total_instructions as f64 }
+  }
+
+  pub fn write_report<W: Write>(&self, out: &mut W) -> io::Result<()> {
+    let avg_draws = if self.frames == 0 { 0.0 }
+                    else { self.total_draws as f64 / self.frames as f64 };
+
+    writeln!(out, "instructions executed: {}", self.total_instructions)?;
+    writeln!(out, "frames: {} ({:.2}s)", self.frames, self.frames as f64 / 60.0)?;
+    writeln!(out, "draws: {} (avg {:.2}/frame, max {}/frame)",
+             self.total_draws, avg_draws, self.max_draws_per_frame)?;
+    writeln!(out, "waiting for key: {} cycles, {} frames ({:.2}s)",
+             self.key_wait_cycles, self.key_wait_frames,
+             self.key_wait_frames as f64 / 60.0)?;
+
+    writeln!(out, "\nhot spots:")?;
+    writeln!(out, "{:>6} {:>12} {:>7}", "addr", "count", "%")?;
+    for (addr, n) in self.hot_spots() {
+      writeln!(out, "{:>6} {:>12} {:>6.2}%",
+               format!("{:03x}", addr), n, self.percent(n))?;
+    }
+
+    writeln!(out, "\nsubroutines:")?;
+    writeln!(out, "{:>6} {:>10} {:>12} {:>7} {:>12} {:>7}",
+             "addr", "calls", "self", "%", "total", "%")?;
+    for (addr, s) in self.hot_subroutines() {
+      writeln!(out, "{:>6} {:>10} {:>12} {:>6.2}% {:>12} {:>6.2}%",
+               format!("{:03x}", addr), s.calls,
+               s.self_instructions, self.percent(s.self_instructions),
+               s.total_instructions, self.percent(s.total_instructions))?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CALL_300: u16 = 0x2300;
+  const RET: u16 = 0x00EE;
+  const NOP: u16 = 0x6000;
+
+  fn run(p: &mut Profiler, program: &[(usize, u16)]) {
+    for &(pc, opcode) in program {
+      p.instruction(pc, opcode);
+    }
+  }
+
+  fn sub(p: &Profiler, addr: usize) -> Subroutine {
+    p.hot_subroutines().into_iter().find(|s| s.0 == addr).unwrap().1
+  }
+
+  #[test]
+  fn counts_subroutines() {
+    let mut p = Profiler::new();
+    run(&mut p, &[(0x200, CALL_300), (0x300, NOP), (0x302, RET),
+                  (0x202, CALL_300), (0x300, NOP), (0x302, RET),
+                  (0x204, NOP)]);
+
+    assert_eq!(p.total_instructions, 7);
+    assert_eq!(p.instructions[0x300], 2);
+    assert_eq!(p.hot_spots()[0], (0x300, 2));
+
+    let s = sub(&p, 0x300);
+    assert_eq!((s.calls, s.self_instructions, s.total_instructions), (2, 4, 4));
+    let main = sub(&p, MAIN_ENTRY);
+    assert_eq!((main.self_instructions, main.total_instructions), (3, 7));
+  }
+
+  #[test]
+  fn counts_recursion_once() {
+    let mut p = Profiler::new();
+    // 300 calls itself once, and the outer call is still running
+    run(&mut p, &[(0x200, CALL_300), (0x300, CALL_300), (0x300, NOP),
+                  (0x302, RET), (0x302, NOP)]);
+    let s = sub(&p, 0x300);
+    assert_eq!((s.calls, s.total_instructions), (2, 4));
+
+    run(&mut p, &[(0x304, RET)]);
+    let s = sub(&p, 0x300);
+    assert_eq!(s.total_instructions, 5);
+    assert!(s.total_instructions <= p.total_instructions);
+  }
+
+  #[test]
+  fn forgets_calls_on_reset() {
+    let mut p = Profiler::new();
+    run(&mut p, &[(0x200, CALL_300), (0x300, NOP)]);
+    p.reset_call_stack();
+    run(&mut p, &[(0x200, NOP), (0x202, RET)]);
+
+    // Calls made before the reset are dropped, not ended by the return
+    assert_eq!(sub(&p, 0x300).total_instructions, 0);
+    assert_eq!(sub(&p, MAIN_ENTRY).total_instructions, 2);
+    assert_eq!(p.total_instructions, 4);
+  }
+}
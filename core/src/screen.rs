@@ -0,0 +1,169 @@
+use crate::{Collision, DrawOrigin};
+
+pub const SCREEN_HEIGHT: usize = 32;
+pub const SCREEN_WIDTH: usize = 64;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Simple logical screen implementation backed by a pixel array.  For each
+// pixel, we also remember the last draw that toggled it.
+
+pub struct PixelScreen {
+  pixels: [u8; SCREEN_HEIGHT * SCREEN_WIDTH],
+  origins: [Option<DrawOrigin>; SCREEN_HEIGHT * SCREEN_WIDTH],
+  current_origin: Option<DrawOrigin>,
+}
+
+impl Default for PixelScreen {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl PixelScreen {
+  pub fn new() -> Self {
+    PixelScreen {
+      pixels: [0; SCREEN_HEIGHT * SCREEN_WIDTH],
+      origins: [None; SCREEN_HEIGHT * SCREEN_WIDTH],
+      current_origin: None,
+    }
+  }
+
+  pub fn draw_pixel(&mut self, p: u8, x: usize, y: usize) -> u8 {
+    let x = x % SCREEN_WIDTH;
+    let y = y % SCREEN_HEIGHT;
+
+    let pos = y * SCREEN_WIDTH + x;
+    let collision = p & self.pixels[pos];
+    self.pixels[pos] ^= p;
+
+    if p != 0 && self.current_origin.is_some() {
+      self.origins[pos] = self.current_origin;
+    }
+
+    collision
+  }
+
+  pub fn pixels(&self) -> &[u8] {
+    &self.pixels
+  }
+
+  pub fn origin(&self, x: usize, y: usize) -> Option<DrawOrigin> {
+    self.origins[(y % SCREEN_HEIGHT) * SCREEN_WIDTH + (x % SCREEN_WIDTH)]
+  }
+}
+
+
+impl crate::Screen for PixelScreen {
+  fn clear(&mut self) {
+    for p in self.pixels.iter_mut() {
+      *p = 0
+    }
+    for o in self.origins.iter_mut() {
+      *o = None
+    }
+  }
+
+  fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8],
+                 clip: bool) -> Collision {
+    let width = 8;
+    let height = sprite.len() / 8;
+    let x = x % SCREEN_WIDTH;
+    let y = y % SCREEN_HEIGHT;
+    let mut collision = Collision::default();
+
+    for yy in 0..height {
+      if clip && y + yy >= SCREEN_HEIGHT {
+        collision.clipped_rows += 1;
+        continue;
+      }
+
+      let mut row_collision = 0;
+      for xx in 0..width {
+        if clip && x + xx >= SCREEN_WIDTH {
+          break;
+        }
+        row_collision |= self.draw_pixel(sprite[yy * width + xx],
+                                         x + xx, y + yy);
+      }
+      collision.rows += row_collision;
+    }
+
+    collision
+  }
+
+  fn trace_draw(&mut self, origin: DrawOrigin) {
+    self.current_origin = Some(origin);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Screen;
+
+  const BLOCK: [u8; 16] = [1; 16];
+
+  #[test]
+  fn sprites_wrap_around_edges() {
+    let mut screen = PixelScreen::new();
+    screen.draw_sprite(SCREEN_WIDTH - 4, SCREEN_HEIGHT - 1, &BLOCK, false);
+
+    assert_eq!(screen.pixels()[(SCREEN_HEIGHT - 1) * SCREEN_WIDTH], 1);
+    assert_eq!(screen.pixels()[SCREEN_WIDTH - 1], 1);
+    assert_eq!(screen.pixels()[0], 1);
+  }
+
+  #[test]
+  fn sprites_clip_at_edges() {
+    let mut screen = PixelScreen::new();
+    let c = screen.draw_sprite(SCREEN_WIDTH - 4, SCREEN_HEIGHT - 1, &BLOCK, true);
+
+    assert_eq!(screen.pixels()[SCREEN_HEIGHT * SCREEN_WIDTH - 1], 1);
+    assert_eq!(screen.pixels()[(SCREEN_HEIGHT - 1) * SCREEN_WIDTH], 0);
+    assert_eq!(screen.pixels()[SCREEN_WIDTH - 1], 0);
+    assert_eq!(screen.pixels()[0], 0);
+    assert_eq!(c.clipped_rows, 1);
+    assert_eq!(c.rows, 0);
+  }
+
+  #[test]
+  fn starting_coordinates_wrap_when_clipping() {
+    let mut screen = PixelScreen::new();
+    screen.draw_sprite(SCREEN_WIDTH + 1, SCREEN_HEIGHT, &BLOCK, true);
+
+    assert_eq!(screen.pixels()[1], 1);
+    assert_eq!(screen.pixels()[SCREEN_WIDTH + 8], 1);
+  }
+
+  #[test]
+  fn collisions_are_counted_per_row() {
+    let mut screen = PixelScreen::new();
+    screen.draw_sprite(0, 0, &BLOCK[..8], false);
+    let c = screen.draw_sprite(4, 0, &BLOCK, false);
+
+    assert_eq!(c.rows, 1);
+    assert_eq!(c.clipped_rows, 0);
+  }
+
+  #[test]
+  fn pixels_remember_their_last_draw() {
+    let mut screen = PixelScreen::new();
+    screen.draw_sprite(0, 0, &BLOCK[..8], false);
+    assert!(screen.origin(0, 0).is_none());
+
+    screen.trace_draw(DrawOrigin { pc: 0x200, ..Default::default() });
+    screen.draw_sprite(0, 0, &BLOCK[..8], false);
+    screen.trace_draw(DrawOrigin { pc: 0x204, ..Default::default() });
+    screen.draw_sprite(4, 0, &BLOCK[..8], false);
+
+    assert_eq!(screen.origin(0, 0).map(|o| o.pc), Some(0x200));
+    // Erasing a pixel counts as drawing it
+    assert_eq!(screen.origin(4, 0).map(|o| o.pc), Some(0x204));
+    assert!(screen.origin(0, 1).is_none());
+
+    screen.clear();
+    assert!(screen.origin(0, 0).is_none());
+    assert!(screen.origin(4, 0).is_none());
+  }
+}
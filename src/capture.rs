@@ -0,0 +1,291 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::{Child, Command, Stdio};
+
+use crate::Args;
+use crate::chip8::screen::PixelScreen;
+use crate::palette::Palette;
+use crate::status;
+use crate::swscreen::Rasterizer;
+
+// GIF delays are in hundredths of a second, and most viewers slow down
+// animations with shorter delays than this
+const GIF_MIN_DELAY_CS: usize = 2;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Screenshots and recordings of the logical screen
+
+pub fn save_png(path: &str, rasterizer: &mut Rasterizer,
+                screen: &PixelScreen) -> io::Result<()> {
+  let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?),
+                                      rasterizer.width() as u32,
+                                      rasterizer.height() as u32);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder.write_header()?.write_image_data(rasterizer.render(screen))?;
+  Ok(())
+}
+
+enum Sink {
+  Gif(gif::Encoder<BufWriter<File>>),
+  // Raw RGBA frames written to the standard input of an external encoder
+  Pipe(Child),
+}
+
+pub struct Recorder {
+  rasterizer: Rasterizer,
+  sink: Sink,
+  // Only one frame out of `step` is kept, each shown for `delay`
+  step: usize,
+  delay: u16,
+  frames: usize,
+}
+
+impl Recorder {
+  pub fn gif(path: &str, rasterizer: Rasterizer,
+             fps: usize) -> io::Result<Self> {
+    let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?),
+                                        rasterizer.width() as u16,
+                                        rasterizer.height() as u16, &[])
+      .map_err(gif_error)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+
+    let (step, delay) = gif_timing(fps);
+    Ok(Self {
+      rasterizer,
+      sink: Sink::Gif(encoder),
+      step,
+      delay,
+      frames: 0,
+    })
+  }
+
+  // Run `command` in a shell, replacing {width}, {height} and {fps}
+  pub fn pipe(command: &str, rasterizer: Rasterizer,
+              fps: usize) -> io::Result<Self> {
+    let command = command
+      .replace("{width}", &rasterizer.width().to_string())
+      .replace("{height}", &rasterizer.height().to_string())
+      .replace("{fps}", &fps.to_string());
+    let child = Command::new("sh")
+      .arg("-c")
+      .arg(command)
+      .stdin(Stdio::piped())
+      .spawn()?;
+
+    Ok(Self {
+      rasterizer,
+      sink: Sink::Pipe(child),
+      step: 1,
+      delay: 0,
+      frames: 0,
+    })
+  }
+
+  pub fn frame(&mut self, screen: &PixelScreen) -> io::Result<()> {
+    self.frames += 1;
+    // Always render, to keep the persistence of pixels up to date
+    let width = self.rasterizer.width() as u16;
+    let height = self.rasterizer.height() as u16;
+    let rgba = self.rasterizer.render(screen);
+    if !(self.frames - 1).is_multiple_of(self.step) {
+      return Ok(())
+    }
+
+    match self.sink {
+      Sink::Gif(ref mut encoder) => {
+        let mut frame = match index_colors(rgba) {
+          Some((pixels, palette)) =>
+            gif::Frame::from_palette_pixels(width, height, pixels, palette,
+                                            None),
+          None => gif::Frame::from_rgba_speed(width, height,
+                                              &mut rgba.to_vec(), 10),
+        };
+        frame.delay = self.delay;
+        encoder.write_frame(&frame).map_err(gif_error)
+      },
+
+      Sink::Pipe(ref mut child) => {
+        child.stdin.as_mut().unwrap().write_all(rgba)
+      },
+    }
+  }
+
+  pub fn finish(self) -> io::Result<()> {
+    match self.sink {
+      // Writes the trailer
+      Sink::Gif(encoder) => encoder.into_inner()?.flush(),
+
+      Sink::Pipe(mut child) => {
+        // Close the pipe to signal the end of the stream
+        drop(child.stdin.take());
+        let status = child.wait()?;
+        if status.success() { Ok(()) }
+        else { Err(io::Error::other(format!("encoder exited with {}", status))) }
+      },
+    }
+  }
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+  io::Error::other(e)
+}
+
+// Return how many frames to skip, and the delay of kept frames, to play back
+// at the same speed as `fps`
+fn gif_timing(fps: usize) -> (usize, u16) {
+  let fps = fps.max(1);
+  let step = (GIF_MIN_DELAY_CS * fps).div_ceil(100);
+  let delay = (100 * step + fps / 2) / fps;
+  (step.max(1), delay as u16)
+}
+
+// Convert an RGBA buffer to palette indices and an RGB palette, if it has at
+// most 256 colors
+fn index_colors(rgba: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+  let mut colors: Vec<&[u8]> = Vec::new();
+  let mut pixels = Vec::with_capacity(rgba.len() / 4);
+
+  for c in rgba.chunks(4) {
+    let rgb = &c[0..3];
+    let index = match colors.iter().position(|&k| k == rgb) {
+      Some(i) => i,
+      None if colors.len() < 256 => { colors.push(rgb); colors.len() - 1 },
+      None => return None,
+    };
+    pixels.push(index as u8);
+  }
+
+  Some((pixels, colors.concat()))
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Capture state shared by all front-ends
+
+pub struct Capture {
+  scale: usize,
+  palette: Palette,
+  grid: bool,
+  persistence: f32,
+  record_path: Option<String>,
+  record_cmd: Option<String>,
+  screenshot_path: Option<String>,
+  fps: usize,
+  recorder: Option<Recorder>,
+}
+
+impl Capture {
+  // Start recording right away if asked on the command line
+  pub fn new(args: &Args, palette: Palette) -> Self {
+    let mut c = Self {
+      scale: args.flag_capture_scale,
+      palette,
+      grid: args.flag_grid,
+      persistence: args.flag_persistence,
+      record_path: args.flag_record.clone(),
+      record_cmd: args.flag_record_cmd.clone(),
+      screenshot_path: args.flag_screenshot.clone(),
+      fps: args.flag_fps,
+      recorder: None,
+    };
+
+    if c.record_path.is_some() || c.record_cmd.is_some() {
+      c.toggle_recording();
+    }
+    c
+  }
+
+  // Persistence needs the previous frames, so only recordings use it
+  fn rasterizer(&self, persistence: f32) -> Rasterizer {
+    let mut r = Rasterizer::new(self.scale);
+    r.palette = self.palette;
+    r.grid = self.grid;
+    r.persistence = persistence;
+    r
+  }
+
+  // Save a screenshot with a timestamped name in the current directory
+  pub fn screenshot(&self, screen: &PixelScreen) {
+    let path = format!("chipers-{}.png", timestamp());
+    match save_png(&path, &mut self.rasterizer(0.0), screen) {
+      Ok(()) => status::post(format!("Saved screenshot to {}", path)),
+      Err(e) => status::post(format!("Error saving screenshot: {}", e)),
+    }
+  }
+
+  pub fn is_recording(&self) -> bool {
+    self.recorder.is_some()
+  }
+
+  pub fn toggle_recording(&mut self) {
+    if let Some(r) = self.recorder.take() {
+      match r.finish() {
+        Ok(()) => status::post("Recording stopped"),
+        Err(e) => status::post(format!("Error finishing recording: {}", e)),
+      }
+      return
+    }
+
+    let r = self.rasterizer(self.persistence);
+    let recorder = match self.record_cmd {
+      Some(ref cmd) => Recorder::pipe(cmd, r, self.fps),
+      None => {
+        // Later recordings do not overwrite the first one
+        let path = self.record_path.take()
+          .unwrap_or_else(|| format!("chipers-{}.gif", timestamp()));
+        status::post(format!("Recording to {}", path));
+        Recorder::gif(&path, r, self.fps)
+      },
+    };
+
+    match recorder {
+      Ok(r) => self.recorder = Some(r),
+      Err(e) => status::post(format!("Error starting recording: {}", e)),
+    }
+  }
+
+  // Add a rendered frame to the recording, if any
+  pub fn frame(&mut self, screen: &PixelScreen) {
+    if let Some(ref mut r) = self.recorder {
+      if let Err(e) = r.frame(screen) {
+        status::post(format!("Error recording frame: {}", e));
+        self.recorder = None;
+      }
+    }
+  }
+
+  // Stop recording and save the final screenshot, if asked
+  pub fn finish(mut self, screen: &PixelScreen) {
+    if self.is_recording() {
+      self.toggle_recording();
+    }
+    if let Some(path) = self.screenshot_path.take() {
+      save_png(&path, &mut self.rasterizer(0.0), screen)
+        .expect("Error saving screenshot");
+    }
+  }
+}
+
+fn timestamp() -> String {
+  time::now().strftime("%Y%m%d-%H%M%S").unwrap().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn gif_timing_keeps_playback_speed() {
+    assert_eq!(gif_timing(60), (2, 3));
+    assert_eq!(gif_timing(50), (1, 2));
+    assert_eq!(gif_timing(30), (1, 3));
+  }
+
+  #[test]
+  fn indexes_colors_in_order_of_appearance() {
+    let rgba = [1, 2, 3, 255, 4, 5, 6, 255, 1, 2, 3, 255];
+    let (pixels, palette) = index_colors(&rgba).unwrap();
+    assert_eq!(pixels, vec![0, 1, 0]);
+    assert_eq!(palette, vec![1, 2, 3, 4, 5, 6]);
+  }
+}
@@ -0,0 +1,328 @@
+use std::fs;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chip8::Timing;
+use crate::chip8::keyboard::SimpleKeyboard;
+use crate::chip8::screen::{PixelScreen, SCREEN_WIDTH};
+use crate::palette::Palette;
+use crate::romdb::RomInfo;
+use crate::{Args, Machine, loader, palette};
+
+// Size of cartridge images
+const WIDTH: usize = 160;
+const HEIGHT: usize = 128;
+// The label is the screen of the ROM, at twice its size
+const LABEL_ZOOM: usize = 2;
+const LABEL_X: usize = (WIDTH - SCREEN_WIDTH * LABEL_ZOOM) / 2;
+const LABEL_Y: usize = 24;
+const BODY_COLOR: [u8; 3] = [0x30, 0x30, 0x30];
+// Byte literals per line of the program
+const LISTING_WIDTH: usize = 16;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Octo cartridges are GIF images carrying a JSON payload:
+//
+//   {"program": ": main 0x00 0xE0 ...", "options": {"tickrate": 20, ...}}
+//
+// preceded by its length on 4 bytes, big-endian.  Each byte is spread over
+// the low 2 bits of the color indices of 4 consecutive pixels, high bits
+// first, and continues on the next frames when the first one is full.
+//
+// The program is Octo source.  Without an assembler, only programs made of
+// byte literals are loaded, and only those are written.
+
+#[derive(Deserialize, Serialize, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct Cart {
+  pub program: String,
+  pub options: Options,
+}
+
+// The options chipers understands; others are ignored
+#[derive(Deserialize, Serialize, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Options {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tickrate: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub background_color: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fill_color: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fill_color2: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub blend_color: Option<String>,
+  pub v_blank_quirks: bool,
+  pub clip_quirks: bool,
+  // Always on in chipers, which shifts Vx in place and leaves I unchanged by
+  // Fx55 and Fx65, so only written
+  pub shift_quirks: bool,
+  pub load_store_quirks: bool,
+}
+
+impl Cart {
+  // Cartridge of `rom`, as a byte listing
+  pub fn new(rom: &[u8], options: Options) -> Self {
+    let mut program = ": main\n".to_string();
+    for line in rom.chunks(LISTING_WIDTH) {
+      let bytes: Vec<String> = line.iter().map(|b| format!("0x{:02X}", b)).collect();
+      program.push_str(&bytes.join(" "));
+      program.push('\n');
+    }
+
+    Self { program, options }
+  }
+
+  // The bytes of the program
+  pub fn rom(&self) -> io::Result<Vec<u8>> {
+    assemble_bytes(&self.program).map_err(invalid)
+  }
+
+  // Settings of the cartridge, as an entry of the ROM database
+  pub fn info(&self) -> RomInfo {
+    let o = &self.options;
+    let palette = if o.background_color.is_some() || o.fill_color.is_some()
+      || o.fill_color2.is_some() || o.blend_color.is_some() {
+      // Missing colors are the ones of Octo
+      let octo = Palette::theme("octo").unwrap();
+      let color = |c: &Option<String>, i: usize| c.as_ref()
+        .map(|c| c.trim_start_matches('#').to_string())
+        .unwrap_or_else(|| format!("{:02x}{:02x}{:02x}", octo.colors[i][0],
+                                   octo.colors[i][1], octo.colors[i][2]));
+      Some(format!("{},{},{},{}", color(&o.background_color, 0),
+                   color(&o.fill_color, 1), color(&o.fill_color2, 2),
+                   color(&o.blend_color, 3)))
+    } else {
+      None
+    };
+
+    RomInfo {
+      ipf: o.tickrate,
+      display_wait: o.v_blank_quirks,
+      clip: o.clip_quirks,
+      palette,
+      ..RomInfo::default()
+    }
+  }
+
+  // GIF image of the cartridge, labelled with `screen`
+  pub fn encode(&self, screen: &PixelScreen,
+                palette: &Palette) -> io::Result<Vec<u8>> {
+    let json = serde_json::to_vec(self).map_err(invalid)?;
+    let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+    payload.extend(json);
+
+    // Colors of the image are 0 to 3 for the screen, and 4 for the body.
+    // Each comes in 4 shades that can't be told apart, one for each value of
+    // the 2 bits stored in a pixel.
+    let mut colors = palette.colors.to_vec();
+    colors.push(BODY_COLOR);
+    let mut gif_palette: Vec<u8> = colors.iter()
+      .flat_map(|c| (0..4).flat_map(move |bits| c.map(|v| v ^ bits)))
+      .collect();
+    gif_palette.resize(32 * 3, 0);
+
+    let mut image = vec![4; WIDTH * HEIGHT];
+    for (i, &p) in screen.pixels().iter().enumerate() {
+      let x0 = LABEL_X + (i % SCREEN_WIDTH) * LABEL_ZOOM;
+      let y0 = LABEL_Y + (i / SCREEN_WIDTH) * LABEL_ZOOM;
+      for y in y0..y0 + LABEL_ZOOM {
+        image[y * WIDTH + x0..y * WIDTH + x0 + LABEL_ZOOM].fill(p & 3);
+      }
+    }
+
+    let bits: Vec<u8> = payload.iter()
+      .flat_map(|b| [b >> 6, (b >> 4) & 3, (b >> 2) & 3, b & 3])
+      .collect();
+    let mut data = Vec::new();
+    {
+      let mut encoder = gif::Encoder::new(&mut data, WIDTH as u16, HEIGHT as u16,
+                                          &gif_palette).map_err(invalid)?;
+      for chunk in bits.chunks(WIDTH * HEIGHT) {
+        let pixels: Vec<u8> = image.iter().enumerate()
+          .map(|(i, c)| (c << 2) | chunk.get(i).unwrap_or(&0))
+          .collect();
+        let frame = gif::Frame::from_indexed_pixels(WIDTH as u16, HEIGHT as u16,
+                                                    pixels, None);
+        encoder.write_frame(&frame).map_err(invalid)?;
+      }
+    }
+    Ok(data)
+  }
+}
+
+// Write the ROM with its current settings to a cartridge at `path`.  The label
+// shows the screen after running the ROM for --frames frames.
+pub fn export(args: &Args, chip8: &mut Machine, path: &str) -> io::Result<()> {
+  let rom = loader::load(&args.arg_rom)?.program;
+
+  let tickrate = match chip8.timing {
+    Timing::PerFrame(ipf) => ipf,
+    _ => ((chip8.freq as f32 / 60.0).round() as u32).max(1),
+  };
+  let colors = palette(args).map(|p| p.colors.map(|[r, g, b]| {
+    Some(format!("#{:02X}{:02X}{:02X}", r, g, b))
+  }));
+  let [background_color, fill_color, fill_color2, blend_color] =
+    colors.unwrap_or_default();
+  let options = Options {
+    tickrate: Some(tickrate),
+    background_color,
+    fill_color,
+    fill_color2,
+    blend_color,
+    v_blank_quirks: chip8.cpu.quirks.display_wait,
+    clip_quirks: chip8.cpu.quirks.clip_sprites,
+    shift_quirks: true,
+    load_store_quirks: true,
+  };
+
+  let mut screen = PixelScreen::new();
+  let mut keyboard = SimpleKeyboard::new();
+  let frame_ms = 1000.0 / args.flag_fps.max(1) as f32;
+  for _ in 0..args.flag_frames {
+    chip8.run(frame_ms, &mut screen, &mut keyboard);
+  }
+
+  let data = Cart::new(&rom, options)
+    .encode(&screen, &palette(args).unwrap_or_default())?;
+  fs::write(path, data)
+}
+
+pub fn decode(data: &[u8]) -> io::Result<Cart> {
+  let payload = payload(data)?;
+  serde_json::from_slice(&payload).map_err(invalid)
+}
+
+// Bytes hidden in the frames of the GIF
+fn payload(data: &[u8]) -> io::Result<Vec<u8>> {
+  let mut options = gif::DecodeOptions::new();
+  options.set_color_output(gif::ColorOutput::Indexed);
+  let mut decoder = options.read_info(data).map_err(invalid)?;
+
+  let mut bytes = Vec::new();
+  while let Some(frame) = decoder.read_next_frame().map_err(invalid)? {
+    bytes.extend(frame.buffer.chunks_exact(4).map(|p| {
+      p.iter().fold(0, |b, i| (b << 2) | (i & 3))
+    }));
+  }
+
+  if bytes.len() < 4 {
+    return Err(invalid("not an Octo cartridge"))
+  }
+  let len = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+  if len > bytes.len() - 4 {
+    return Err(invalid("not an Octo cartridge"))
+  }
+  Ok(bytes[4..4 + len].to_vec())
+}
+
+const NOT_BYTES: &str = "the cartridge holds Octo source, and only byte \
+                         listings like \"0x00 0xE0\" can be loaded";
+
+// Octo source made of byte literals, after an optional `: main`
+fn assemble_bytes(source: &str) -> Result<Vec<u8>, String> {
+  let mut tokens = source.lines()
+    .flat_map(|l| l.split('#').next().unwrap_or("").split_whitespace())
+    .peekable();
+  if tokens.peek() == Some(&":") {
+    tokens.next();
+    if tokens.next() != Some("main") {
+      return Err(NOT_BYTES.into())
+    }
+  }
+
+  tokens.map(|t| {
+    let n = if let Some(hex) = t.strip_prefix("0x") {
+      u8::from_str_radix(hex, 16)
+    } else if let Some(bin) = t.strip_prefix("0b") {
+      u8::from_str_radix(bin, 2)
+    } else {
+      t.parse()
+    };
+    n.map_err(|_| format!("'{}': {}", t, NOT_BYTES))
+  }).collect()
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::Path;
+
+  // A one-frame cartridge holding `json`
+  fn cart(json: &str) -> Vec<u8> {
+    let mut bytes = (json.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(json.as_bytes());
+    let mut pixels: Vec<u8> = bytes.iter()
+      .flat_map(|b| vec![b >> 6, (b >> 4) & 3, (b >> 2) & 3, b & 3])
+      .collect();
+    pixels.resize(64 * 64, 0);
+
+    let mut data = Vec::new();
+    {
+      let palette = [0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3];
+      let mut encoder = gif::Encoder::new(&mut data, 64, 64, &palette).unwrap();
+      let frame = gif::Frame::from_indexed_pixels(64, 64, pixels, None);
+      encoder.write_frame(&frame).unwrap();
+    }
+    data
+  }
+
+  #[test]
+  fn round_trips() {
+    // Large enough to need several frames
+    let rom: Vec<u8> = (0..3584).map(|i| (i * 7) as u8).collect();
+    let options = Options {
+      tickrate: Some(100),
+      fill_color: Some("#FFFFFF".to_string()),
+      clip_quirks: true,
+      shift_quirks: true,
+      load_store_quirks: true,
+      ..Options::default()
+    };
+    let written = Cart::new(&rom, options);
+    let data = written.encode(&PixelScreen::new(), &Palette::default()).unwrap();
+
+    let decoded = decode(&data).unwrap();
+    assert_eq!(decoded, written);
+    assert_eq!(decoded.rom().unwrap(), rom);
+
+    let loaded = loader::decode(Path::new("rom.gif"), data).unwrap();
+    assert_eq!(loaded.program, rom);
+    assert_eq!(loaded.embedded.unwrap().ipf, Some(100));
+  }
+
+  #[test]
+  fn decodes_cartridges() {
+    let data = cart(r##"{"program": ": main\n0x00 0xE0 # clear\n18 0b1",
+                         "options": {"tickrate": 15, "vBlankQuirks": true,
+                                     "fillColor": "#FFFFFF",
+                                     "screenRotation": 0}}"##);
+    let decoded = decode(&data).unwrap();
+    assert_eq!(decoded.rom().unwrap(), vec![0x00, 0xe0, 18, 1]);
+
+    let info = decoded.info();
+    assert_eq!(info.ipf, Some(15));
+    assert!(info.display_wait);
+    assert!(!info.clip);
+    assert_eq!(info.palette.as_deref(), Some("996600,FFFFFF,ff6600,662200"));
+
+    assert!(decode(&cart("{}")).unwrap().info().palette.is_none());
+    assert!(decode(&cart("not json")).is_err());
+    assert!(decode(b"GIF89a").is_err());
+  }
+
+  #[test]
+  fn loads_byte_listings_only() {
+    assert_eq!(assemble_bytes("0xff 255 # all ones"), Ok(vec![0xff, 0xff]));
+    assert!(assemble_bytes(": main clear").is_err());
+    assert!(assemble_bytes(": start 0x00").is_err());
+    assert!(assemble_bytes("256").is_err());
+  }
+}
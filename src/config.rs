@@ -0,0 +1,54 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// User configuration files
+
+// $XDG_CONFIG_HOME/chipers, or the platform equivalent
+pub fn config_dir() -> Option<PathBuf> {
+  env::var_os("XDG_CONFIG_HOME")
+    .filter(|d| !d.is_empty())
+    .map(PathBuf::from)
+    .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+    .map(|d| d.join("chipers"))
+}
+
+// Path of a file in the configuration directory, which is created if needed
+pub fn file_path(name: &str) -> Option<PathBuf> {
+  let dir = config_dir()?;
+  fs::create_dir_all(&dir).ok()?;
+  Some(dir.join(name))
+}
+
+// Read a TOML file from the configuration directory.  A missing or invalid
+// file gives the default value.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+  let path = match config_dir() {
+    Some(d) => d.join(name),
+    None => return T::default(),
+  };
+
+  match fs::read_to_string(&path) {
+    Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+      eprintln!("Error in {}: {}", path.display(), e);
+      T::default()
+    }),
+    Err(_) => T::default(),
+  }
+}
+
+// Write a TOML file to the configuration directory, creating it if needed
+pub fn save<T: Serialize>(name: &str, value: &T) -> io::Result<()> {
+  let dir = config_dir()
+    .ok_or_else(|| io::Error::other("no configuration directory"))?;
+  let text = toml::to_string(value).map_err(io::Error::other)?;
+
+  fs::create_dir_all(&dir)?;
+  fs::write(dir.join(name), text)
+}
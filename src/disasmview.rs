@@ -0,0 +1,59 @@
+use imgui::{Ui, ImStr, im_str};
+
+use crate::chip8::disasm::disassemble;
+
+const ROWS: usize = 32;
+
+pub struct DisassemblyView {
+  open: bool,
+  // Address to show instead of following the PC
+  target: Option<usize>,
+}
+
+impl DisassemblyView {
+  pub fn new() -> DisassemblyView {
+    DisassemblyView {
+      open: true,
+      target: None,
+    }
+  }
+
+  pub fn goto(&mut self, addr: usize) {
+    self.target = Some(addr);
+    self.open = true;
+  }
+
+  pub fn draw(&mut self, ui: &Ui, title: &ImStr, mem: &[u8], pc: usize) {
+    let target = &mut self.target;
+    let center = target.unwrap_or(pc);
+    // Keep the same alignment as the centered instruction
+    let start = center - (center.min(ROWS) & !1);
+
+    ui.window(title)
+      .opened(&mut self.open)
+      .build(|| {
+        if target.is_some() && ui.small_button(im_str!("follow pc")) {
+          *target = None;
+        }
+
+        let mut a = start;
+        for _ in 0..ROWS {
+          if a + 1 >= mem.len() {
+            break;
+          }
+
+          let opcode = ((mem[a] as u16) << 8) | (mem[a + 1] as u16);
+          let marker = if a == pc { ">" } else { " " };
+          let line = im_str!("{} {:03x}: {:04x} {}", marker, a, opcode,
+                             disassemble(opcode));
+
+          if Some(a) == *target {
+            ui.text_colored([1.0, 1.0, 0.0, 1.0], line);
+          } else {
+            ui.text(line);
+          }
+          a += 2;
+        }
+      });
+  }
+}
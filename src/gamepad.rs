@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::chip8::keyboard::{KeySource, SimpleKeyboard};
+use crate::config;
+use crate::status;
+
+// How far a stick must be pushed to press a key
+const DEFAULT_THRESHOLD: f32 = 0.5;
+
+// Gamepad inputs at the usual directions and buttons of CHIP-8 games
+const DEFAULT_BUTTONS: [(&str, u8); 16] = [
+  ("DPadUp", 0x2),
+  ("DPadDown", 0x8),
+  ("DPadLeft", 0x4),
+  ("DPadRight", 0x6),
+  ("LeftStickY+", 0x2),
+  ("LeftStickY-", 0x8),
+  ("LeftStickX-", 0x4),
+  ("LeftStickX+", 0x6),
+  ("DPadY+", 0x2),
+  ("DPadY-", 0x8),
+  ("DPadX-", 0x4),
+  ("DPadX+", 0x6),
+  ("South", 0x5),
+  ("West", 0x4),
+  ("North", 0xE),
+  ("Start", 0xF),
+];
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Mapping from gamepad buttons and axes to the 16 hex keys
+
+// Inputs are named like the buttons and axes of gilrs ("South", "DPadUp"...),
+// ignoring case.  Axes are split in two inputs, "LeftStickX-" and
+// "LeftStickX+", pressed when the axis goes past the threshold.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GamepadMap {
+  pub threshold: f32,
+  bindings: BTreeMap<String, u8>,
+}
+
+impl GamepadMap {
+  // Hex key bound to an input
+  pub fn key(&self, input: &str) -> Option<u8> {
+    self.bindings.get(&input.to_lowercase()).cloned()
+  }
+
+  pub fn bind(&mut self, input: &str, key: u8) {
+    self.bindings.insert(input.to_lowercase(), key);
+  }
+
+  fn apply(&mut self, file: &GamepadFile) -> Result<(), String> {
+    if let Some(t) = file.threshold {
+      self.threshold = t;
+    }
+    for (input, key) in &file.buttons {
+      if key.is_empty() {
+        self.bindings.remove(&input.to_lowercase());
+        continue;
+      }
+      self.bind(input, parse_key(key)?);
+    }
+    Ok(())
+  }
+
+  // Buttons of the ROM database replace all the inputs of their keys
+  fn apply_rom(&mut self,
+               rom_buttons: &BTreeMap<String, Vec<String>>) -> Result<(), String> {
+    for (key, inputs) in rom_buttons {
+      let k = parse_key(key)?;
+      self.bindings.retain(|_, &mut b| b != k);
+      for input in inputs {
+        self.bind(input, k);
+      }
+    }
+    Ok(())
+  }
+
+  // Mapping from gamepad.toml, with the buttons of the ROM database
+  pub fn load(rom_buttons: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+    let file: GamepadFile = config::load(GAMEPAD_FILE);
+    file.map(rom_buttons)
+  }
+}
+
+impl Default for GamepadMap {
+  fn default() -> Self {
+    let mut map = GamepadMap {
+      threshold: DEFAULT_THRESHOLD,
+      bindings: BTreeMap::new(),
+    };
+    for &(input, key) in &DEFAULT_BUTTONS {
+      map.bind(input, key);
+    }
+    map
+  }
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// gamepad.toml in the configuration directory:
+//
+//   threshold = 0.3
+//
+//   [buttons]
+//   East = "6"
+//   LeftTrigger = "A"
+//   South = ""
+//
+// Buttons listed replace the default ones, and an empty key unbinds the
+// button.  Bindings for a single ROM go in the ROM database.
+
+const GAMEPAD_FILE: &str = "gamepad.toml";
+
+#[derive(Deserialize, Default)]
+struct GamepadFile {
+  threshold: Option<f32>,
+  #[serde(default)]
+  buttons: BTreeMap<String, String>,
+}
+
+impl GamepadFile {
+  fn map(&self, rom_buttons: &BTreeMap<String, Vec<String>>)
+         -> Result<GamepadMap, String> {
+    let mut map = GamepadMap::default();
+    map.apply(self)?;
+    map.apply_rom(rom_buttons)?;
+    Ok(map)
+  }
+}
+
+fn parse_key(key: &str) -> Result<u8, String> {
+  u8::from_str_radix(key, 16).ok()
+    .filter(|&k| k < 16)
+    .ok_or_else(|| format!("invalid key '{}'", key))
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Events of any gamepad backend, applied to the keyboard.  Only the tests use
+// them without the `gamepad` feature.
+
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+#[derive(Clone, PartialEq, Debug)]
+pub enum PadEvent {
+  Connected(usize, String),
+  Disconnected(usize),
+  Pressed(usize, String),
+  Released(usize, String),
+  Axis(usize, String, f32),
+}
+
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+pub struct GamepadMapper {
+  map: GamepadMap,
+  // Key held by each input of each pad
+  held: BTreeMap<(usize, String), u8>,
+}
+
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+impl GamepadMapper {
+  pub fn new(map: GamepadMap) -> Self {
+    Self {
+      map,
+      held: BTreeMap::new(),
+    }
+  }
+
+  pub fn event(&mut self, event: PadEvent, keyboard: &mut SimpleKeyboard) {
+    match event {
+      PadEvent::Connected(pad, name) => {
+        status::post(format!("Gamepad {} connected: {}", pad, name));
+      },
+
+      PadEvent::Disconnected(pad) => {
+        status::post(format!("Gamepad {} disconnected", pad));
+        let inputs: Vec<String> = self.held.keys()
+          .filter(|&&(p, _)| p == pad)
+          .map(|(_, i)| i.clone())
+          .collect();
+        for input in inputs {
+          self.release(pad, &input, keyboard);
+        }
+      },
+
+      PadEvent::Pressed(pad, input) => self.press(pad, &input, keyboard),
+
+      PadEvent::Released(pad, input) => self.release(pad, &input, keyboard),
+
+      PadEvent::Axis(pad, axis, value) => {
+        let plus = format!("{}+", axis);
+        let minus = format!("{}-", axis);
+        let threshold = self.map.threshold;
+
+        // Release first, in case both halves go to the same key
+        if value <= threshold {
+          self.release(pad, &plus, keyboard);
+        }
+        if value >= -threshold {
+          self.release(pad, &minus, keyboard);
+        }
+        if value > threshold {
+          self.press(pad, &plus, keyboard);
+        }
+        if value < -threshold {
+          self.press(pad, &minus, keyboard);
+        }
+      },
+    }
+  }
+
+  fn press(&mut self, pad: usize, input: &str,
+           keyboard: &mut SimpleKeyboard) {
+    let id = (pad, input.to_lowercase());
+    if self.held.contains_key(&id) {
+      return
+    }
+    if let Some(k) = self.map.key(input) {
+      self.held.insert(id, k);
+      keyboard.press_key_from(KeySource::Gamepad, k);
+    }
+  }
+
+  // The key is only released once no other input holds it.  The keyboard
+  // keeps it down for the other sources.
+  fn release(&mut self, pad: usize, input: &str,
+             keyboard: &mut SimpleKeyboard) {
+    if let Some(k) = self.held.remove(&(pad, input.to_lowercase())) {
+      if !self.held.values().any(|&h| h == k) {
+        keyboard.release_key_from(KeySource::Gamepad, k);
+      }
+    }
+  }
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Gamepads read with gilrs, when built with the `gamepad` feature
+
+#[cfg(feature = "gamepad")]
+pub struct Gamepads {
+  gilrs: Option<gilrs::Gilrs>,
+  mapper: GamepadMapper,
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+  pub fn new(map: GamepadMap) -> Self {
+    let gilrs = gilrs::Gilrs::new()
+      .map_err(|e| status::post(format!("Gamepads unavailable: {}", e)))
+      .ok();
+
+    // Pads plugged in before starting
+    for (id, pad) in gilrs.iter().flat_map(|g| g.gamepads()) {
+      status::post(format!("Gamepad {} connected: {}", usize::from(id),
+                           pad.name()));
+    }
+
+    Self { gilrs, mapper: GamepadMapper::new(map) }
+  }
+
+  // Use the mapping of another ROM
+  pub fn remap(&mut self, map: GamepadMap) {
+    self.mapper = GamepadMapper::new(map);
+  }
+
+  pub fn poll(&mut self, keyboard: &mut SimpleKeyboard) {
+    use gilrs::{Event, EventType};
+
+    let gilrs = match self.gilrs {
+      Some(ref mut g) => g,
+      None => return,
+    };
+
+    while let Some(Event { id, event, .. }) = gilrs.next_event() {
+      let pad = usize::from(id);
+      let event = match event {
+        EventType::Connected =>
+          PadEvent::Connected(pad, gilrs.gamepad(id).name().to_string()),
+        EventType::Disconnected => PadEvent::Disconnected(pad),
+        EventType::ButtonPressed(b, _) => PadEvent::Pressed(pad, format!("{:?}", b)),
+        EventType::ButtonReleased(b, _) => PadEvent::Released(pad, format!("{:?}", b)),
+        EventType::AxisChanged(a, v, _) => PadEvent::Axis(pad, format!("{:?}", a), v),
+        _ => continue,
+      };
+      self.mapper.event(event, keyboard);
+    }
+  }
+}
+
+// Without the feature, there are no gamepads to read
+#[cfg(not(feature = "gamepad"))]
+pub struct Gamepads;
+
+#[cfg(not(feature = "gamepad"))]
+impl Gamepads {
+  pub fn new(_map: GamepadMap) -> Self {
+    Gamepads
+  }
+
+  pub fn remap(&mut self, _map: GamepadMap) {}
+
+  pub fn poll(&mut self, _keyboard: &mut SimpleKeyboard) {}
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::chip8::Keyboard;
+
+  fn pressed(keyboard: &SimpleKeyboard) -> Vec<u8> {
+    (0..16).filter(|&k| keyboard.is_pressed(k)).collect()
+  }
+
+  #[test]
+  fn maps_buttons_and_axes() {
+    let mut mapper = GamepadMapper::new(GamepadMap::default());
+    let mut keyboard = SimpleKeyboard::new();
+
+    mapper.event(PadEvent::Pressed(0, "South".into()), &mut keyboard);
+    mapper.event(PadEvent::Pressed(0, "Mode".into()), &mut keyboard);
+    assert_eq!(pressed(&keyboard), vec![0x5]);
+    mapper.event(PadEvent::Released(0, "South".into()), &mut keyboard);
+    assert!(pressed(&keyboard).is_empty());
+
+    mapper.event(PadEvent::Axis(0, "LeftStickX".into(), 0.3), &mut keyboard);
+    assert!(pressed(&keyboard).is_empty());
+    mapper.event(PadEvent::Axis(0, "LeftStickX".into(), 0.8), &mut keyboard);
+    assert_eq!(pressed(&keyboard), vec![0x6]);
+    mapper.event(PadEvent::Axis(0, "LeftStickX".into(), -0.9), &mut keyboard);
+    assert_eq!(pressed(&keyboard), vec![0x4]);
+    mapper.event(PadEvent::Axis(0, "LeftStickX".into(), 0.0), &mut keyboard);
+    assert!(pressed(&keyboard).is_empty());
+  }
+
+  #[test]
+  fn keeps_keys_held_by_other_inputs() {
+    let mut mapper = GamepadMapper::new(GamepadMap::default());
+    let mut keyboard = SimpleKeyboard::new();
+
+    // West and the left d-pad both go to 4, on two pads
+    mapper.event(PadEvent::Pressed(0, "West".into()), &mut keyboard);
+    mapper.event(PadEvent::Pressed(1, "DPadLeft".into()), &mut keyboard);
+    mapper.event(PadEvent::Released(0, "West".into()), &mut keyboard);
+    assert_eq!(pressed(&keyboard), vec![0x4]);
+
+    // Unplugging a pad releases what it held
+    mapper.event(PadEvent::Pressed(1, "Start".into()), &mut keyboard);
+    mapper.event(PadEvent::Disconnected(1), &mut keyboard);
+    assert!(pressed(&keyboard).is_empty());
+  }
+
+  #[test]
+  fn keeps_keys_held_by_the_keyboard() {
+    let mut mapper = GamepadMapper::new(GamepadMap::default());
+    let mut keyboard = SimpleKeyboard::new();
+
+    keyboard.press_key(0x5);
+    mapper.event(PadEvent::Pressed(0, "South".into()), &mut keyboard);
+    mapper.event(PadEvent::Released(0, "South".into()), &mut keyboard);
+    assert_eq!(pressed(&keyboard), vec![0x5]);
+  }
+
+  #[test]
+  fn applies_rom_buttons() {
+    let file: GamepadFile = toml::from_str(r#"
+      threshold = 0.2
+
+      [buttons]
+      East = "a"
+      West = ""
+    "#).unwrap();
+
+    let map = file.map(&BTreeMap::new()).unwrap();
+    assert_eq!(map.threshold, 0.2);
+    assert_eq!(map.key("east"), Some(0xA));
+    assert_eq!(map.key("West"), None);
+    assert_eq!(map.key("DPadUp"), Some(0x2));
+
+    // The ROM only presses 2 with South, which leaves 5 to East
+    let rom = vec![("2".to_string(), vec!["South".to_string()]),
+                   ("5".to_string(), vec!["East".to_string()])]
+      .into_iter().collect();
+    let map = file.map(&rom).unwrap();
+    assert_eq!(map.key("South"), Some(0x2));
+    assert_eq!(map.key("East"), Some(0x5));
+    assert_eq!(map.key("DPadUp"), None);
+    assert_eq!(map.key("LeftStickY+"), None);
+    assert_eq!(map.key("DPadDown"), Some(0x8));
+
+    let file: GamepadFile = toml::from_str("[buttons]\nSouth = \"g\"").unwrap();
+    assert!(file.map(&BTreeMap::new()).is_err());
+    let rom = vec![("10".to_string(), vec![])].into_iter().collect();
+    assert!(GamepadFile::default().map(&rom).is_err());
+  }
+}
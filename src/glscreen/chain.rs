@@ -0,0 +1,366 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use glium::Surface;
+use glium::backend::Context;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+use glium::texture::texture2d::Texture2d;
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter,
+                      SamplerBehavior, UniformValue, Uniforms};
+use serde::Deserialize;
+
+use super::{SCREEN_WIDTH, SCREEN_HEIGHT, Quad};
+use super::shader::{self, Source};
+use crate::palette::Palette;
+use crate::status;
+
+// History frames used by the builtin phosphor shaders
+const DEFAULT_HISTORY: usize = 7;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Shader passes rendered one after the other, each one reading the output of
+// the previous one.  A preset is a TOML file like:
+//
+//   history = 7
+//
+//   [[pass]]
+//   shader = "phosphor-trail"
+//   scale = 4.0
+//
+//   [[pass]]
+//   shader = "scanlines.glsl"
+//   linear = true
+//   parameters = { strength = { value = 0.5, min = 0.0, max = 1.0 } }
+//
+// Each pass sees the previous output as `tex`, the logical screen as
+// `orig_tex`, past logical screens as `prev0_tex`, `prev1_tex`..., its output
+// size as `iResolution` and its input size as `iSourceSize`.  Shaders get a
+// `pixel_color(value, background, foreground)` function, which returns the
+// colors of the chosen palette if any, and the given ones otherwise.
+
+#[derive(Deserialize)]
+struct PresetFile {
+  history: Option<usize>,
+  pass: Vec<PassFile>,
+}
+
+#[derive(Deserialize)]
+struct PassFile {
+  shader: String,
+  // Output size, relative to the input or to the window.  Ignored for the
+  // last pass, which draws to the window.
+  scale: Option<f32>,
+  #[serde(default)]
+  viewport: bool,
+  // Sample the input with linear filtering instead of nearest
+  #[serde(default)]
+  linear: bool,
+  #[serde(default)]
+  parameters: BTreeMap<String, ParameterFile>,
+}
+
+#[derive(Deserialize)]
+struct ParameterFile {
+  value: Option<f32>,
+  min: Option<f32>,
+  max: Option<f32>,
+}
+
+pub struct Parameter {
+  pub name: String,
+  pub value: f32,
+  pub default: f32,
+  pub min: f32,
+  pub max: f32,
+}
+
+pub struct Pass {
+  source: Source,
+  program: glium::Program,
+  scale: f32,
+  viewport: bool,
+  linear: bool,
+  pub parameters: Vec<Parameter>,
+  target: Option<Texture2d>,
+}
+
+impl Pass {
+  fn new(context: &Rc<Context>, mut source: Source) -> Result<Self, String> {
+    let program = source.compile(context)?;
+    let parameters = match source {
+      Source::Builtin(b) => b.parameters.iter()
+        .map(|&(name, value, min, max)| Parameter {
+          name: name.to_string(), value, default: value, min, max,
+        })
+        .collect(),
+      Source::File { .. } => Vec::new(),
+    };
+
+    Ok(Self {
+      source,
+      program,
+      scale: 1.0,
+      viewport: false,
+      linear: false,
+      parameters,
+      target: None,
+    })
+  }
+
+  pub fn name(&self) -> String {
+    self.source.name()
+  }
+
+  // Override builtin parameters, or add new ones
+  fn set_parameter(&mut self, name: &str, p: &ParameterFile) {
+    if let Some(q) = self.parameters.iter_mut().find(|q| q.name == name) {
+      q.value = p.value.unwrap_or(q.value);
+      q.min = p.min.unwrap_or(q.min);
+      q.max = p.max.unwrap_or(q.max);
+      q.default = q.value;
+    } else {
+      let value = p.value.unwrap_or(0.0);
+      self.parameters.push(Parameter {
+        name: name.to_string(),
+        value,
+        default: value,
+        min: p.min.unwrap_or_else(|| value.min(0.0)),
+        max: p.max.unwrap_or_else(|| value.max(1.0)),
+      });
+    }
+  }
+}
+
+pub struct Chain {
+  name: String,
+  // Path and modification time of the preset file, if any
+  preset: Option<(PathBuf, Option<SystemTime>)>,
+  pub passes: Vec<Pass>,
+  // Number of past logical screens the passes can read
+  pub history: usize,
+}
+
+impl Chain {
+  // Load a builtin shader, a GLSL file, or a TOML preset
+  pub fn load(context: &Rc<Context>, name: &str) -> Result<Self, String> {
+    if !name.ends_with(".toml") {
+      return Ok(Self {
+        name: name.to_string(),
+        preset: None,
+        passes: vec![Pass::new(context, Source::parse(name))?],
+        history: DEFAULT_HISTORY,
+      })
+    }
+
+    let path = PathBuf::from(name);
+    let modified = shader::mtime(&path);
+    let text = fs::read_to_string(&path)
+      .map_err(|e| format!("{}: {}", name, e))?;
+    let preset: PresetFile = toml::from_str(&text)
+      .map_err(|e| format!("{}: {}", name, e))?;
+    if preset.pass.is_empty() {
+      return Err(format!("{}: no passes", name))
+    }
+
+    // Shader paths are relative to the preset
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut passes = Vec::with_capacity(preset.pass.len());
+    for p in &preset.pass {
+      let source = match Source::parse(&p.shader) {
+        Source::File { path, .. } => Source::File {
+          path: dir.join(path),
+          modified: None,
+        },
+        builtin => builtin,
+      };
+
+      let mut pass = Pass::new(context, source)?;
+      pass.scale = p.scale.unwrap_or(1.0);
+      pass.viewport = p.viewport;
+      pass.linear = p.linear;
+      for (name, param) in &p.parameters {
+        pass.set_parameter(name, param);
+      }
+      passes.push(pass);
+    }
+
+    Ok(Self {
+      name: name.to_string(),
+      preset: Some((path, modified)),
+      passes,
+      history: preset.history.unwrap_or(DEFAULT_HISTORY),
+    })
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  // Reload the preset or recompile passes whose files changed.  On errors,
+  // the previous state is kept.
+  pub fn reload(&mut self, context: &Rc<Context>) {
+    if let Some((ref path, modified)) = self.preset {
+      if shader::mtime(path) != modified {
+        match Chain::load(context, &self.name) {
+          Ok(c) => {
+            *self = c;
+            status::post(format!("Reloaded preset {}", self.name));
+          },
+          Err(e) => {
+            // Do not retry until the next change
+            self.preset = Some((path.clone(), shader::mtime(path)));
+            status::post(format!("Error loading preset {}", e));
+          },
+        }
+        return
+      }
+    }
+
+    for pass in &mut self.passes {
+      if pass.source.changed() {
+        match pass.source.compile(context) {
+          Ok(p) => {
+            pass.program = p;
+            status::post(format!("Reloaded shader {}", pass.name()));
+          },
+          Err(e) => status::post(format!("Error compiling shader {}", e)),
+        }
+      }
+    }
+  }
+
+  pub fn draw<S: Surface>(&mut self, context: &Rc<Context>, frame: &mut S,
+                          quad: &Quad, screen: &Texture2d,
+                          history: &VecDeque<Texture2d>,
+                          palette: Option<Palette>) {
+    let window = frame.get_dimensions();
+    let mut input_size = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    let last = self.passes.len() - 1;
+
+    for i in 0..self.passes.len() {
+      let (done, rest) = self.passes.split_at_mut(i);
+      let pass = &mut rest[0];
+
+      // Intermediate passes draw into a texture of their own
+      let output_size = if i == last { window } else {
+        let base = if pass.viewport { window } else { input_size };
+        let size = ((base.0 as f32 * pass.scale).round().max(1.0) as u32,
+                    (base.1 as f32 * pass.scale).round().max(1.0) as u32);
+        if pass.target.as_ref().map(|t| t.dimensions()) != Some(size) {
+          pass.target = Some(Texture2d::empty_with_format(
+            context, UncompressedFloatFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap, size.0, size.1).unwrap());
+        }
+        size
+      };
+
+      let uniforms = PassUniforms {
+        source: if i == 0 { screen } else { done[i - 1].target.as_ref().unwrap() },
+        source_size: input_size,
+        output_size,
+        linear: pass.linear,
+        screen,
+        history,
+        parameters: &pass.parameters,
+        palette,
+      };
+
+      match pass.target {
+        Some(ref target) if i != last => {
+          let mut fb = SimpleFrameBuffer::new(context, target).unwrap();
+          quad.draw(&mut fb, &pass.program, &uniforms);
+        },
+        _ => {
+          quad.draw(frame, &pass.program, &uniforms);
+        },
+      }
+
+      input_size = output_size;
+    }
+  }
+}
+
+struct PassUniforms<'t> {
+  source: &'t Texture2d,
+  source_size: (u32, u32),
+  output_size: (u32, u32),
+  linear: bool,
+  screen: &'t Texture2d,
+  history: &'t VecDeque<Texture2d>,
+  parameters: &'t [Parameter],
+  palette: Option<Palette>,
+}
+
+fn sampler(linear: bool) -> Option<SamplerBehavior> {
+  let (minify_filter, magnify_filter) =
+    if linear { (MinifySamplerFilter::Linear, MagnifySamplerFilter::Linear) }
+    else { (MinifySamplerFilter::Nearest, MagnifySamplerFilter::Nearest) };
+
+  Some(SamplerBehavior {
+    minify_filter,
+    magnify_filter,
+    ..Default::default()
+  })
+}
+
+// Uniforms that a shader does not declare are ignored
+impl<'t> Uniforms for PassUniforms<'t> {
+  fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+    f("iResolution", UniformValue::Vec2([self.output_size.0 as f32,
+                                         self.output_size.1 as f32]));
+    f("iSourceSize", UniformValue::Vec2([self.source_size.0 as f32,
+                                         self.source_size.1 as f32]));
+    f("tex", UniformValue::Texture2d(self.source, sampler(self.linear)));
+    f("orig_tex", UniformValue::Texture2d(self.screen, sampler(false)));
+
+    for (i, t) in self.history.iter().enumerate() {
+      f(&format!("prev{}_tex", i), UniformValue::Texture2d(t, sampler(false)));
+    }
+
+    for p in self.parameters {
+      f(&p.name, UniformValue::Float(p.value));
+    }
+
+    f("use_palette", UniformValue::Bool(self.palette.is_some()));
+    if let Some(ref palette) = self.palette {
+      for (i, c) in palette.colors.iter().enumerate() {
+        f(&format!("palette{}", i),
+          UniformValue::Vec3(c.map(|x| x as f32 / 255.0)));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_presets() {
+    let preset: PresetFile = toml::from_str(r#"
+      [[pass]]
+      shader = "phosphor-trail"
+      scale = 4.0
+
+      [[pass]]
+      shader = "scanlines.glsl"
+      linear = true
+      parameters = { strength = { value = 0.5, max = 2.0 } }
+    "#).unwrap();
+
+    assert_eq!(preset.history, None);
+    assert_eq!(preset.pass.len(), 2);
+    assert_eq!(preset.pass[0].scale, Some(4.0));
+    assert!(!preset.pass[0].linear);
+    assert!(preset.pass[1].linear);
+
+    let strength = &preset.pass[1].parameters["strength"];
+    assert_eq!(strength.value, Some(0.5));
+    assert_eq!(strength.min, None);
+    assert_eq!(strength.max, Some(2.0));
+  }
+}
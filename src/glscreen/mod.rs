@@ -1,16 +1,28 @@
+pub mod chain;
+mod shader;
+
 use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use glium::{IndexBuffer, Program, Surface, VertexBuffer};
-use glium::backend::Facade;
+use glium::{BlitTarget, IndexBuffer, Program, Rect, Surface, VertexBuffer};
+use glium::backend::{Context, Facade};
 use glium::index::PrimitiveType;
 use glium::texture::{MipmapsOption, UncompressedFloatFormat};
 use glium::texture::pixel_buffer::PixelBuffer;
 use glium::texture::texture2d::Texture2d;
-use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
-use glium::{implement_vertex, uniform};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::implement_vertex;
+use glium::uniforms::{MagnifySamplerFilter, Uniforms};
 
-use crate::chip8::{Screen, screen::PixelScreen};
+use crate::chip8::{Collision, DrawOrigin, Screen, screen::PixelScreen};
+use crate::palette::Palette;
+use crate::status;
 pub use crate::chip8::screen::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use self::chain::Chain;
+
+// How often to look for changes to shader files
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -19,30 +31,14 @@ struct Vertex {
 }
 implement_vertex!(Vertex, position, tex_coords);
 
-//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
-// A screen backed by OpenGL surface
-
-pub struct GLScreen {
-  screen: PixelScreen,
-  program: Program,
+// The rectangle every shader pass draws
+pub struct Quad {
   vertex_buffer: VertexBuffer<Vertex>,
   index_buffer: IndexBuffer<u16>,
-  pixel_buffer: PixelBuffer<u8>,
-  texture: Texture2d,
-  past_textures: Option<VecDeque<Texture2d>>,
 }
 
-impl GLScreen {
-  pub fn new<F: Facade>(display: &F, use_plain_shader: bool) -> Self {
-    let fragment = if use_plain_shader { include_str!("shaders/fragment-120.glsl") }
-                   else { include_str!("shaders/crt-phosphor-120.glsl") };
-
-    let program = Program::from_source(
-      display,
-      include_str!("shaders/vertex-120.glsl"),
-      fragment,
-      None).unwrap();
-
+impl Quad {
+  fn new<F: Facade>(display: &F) -> Self {
     // One nice rectangle to hold the texture
     // Texture coordinates are upside-down.
     let vertices = [
@@ -52,9 +48,57 @@ impl GLScreen {
       Vertex { position: [ 1.0, -1.0], tex_coords: [1.0, 1.0] }
     ];
 
-    let vertex_buffer = VertexBuffer::immutable(display, &vertices).unwrap();
-    let index_buffer = IndexBuffer::immutable(
-      display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3]).unwrap();
+    Self {
+      vertex_buffer: VertexBuffer::immutable(display, &vertices).unwrap(),
+      index_buffer: IndexBuffer::immutable(
+        display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3]).unwrap(),
+    }
+  }
+
+  pub fn draw<S: Surface, U: Uniforms>(&self, surface: &mut S,
+                                       program: &Program, uniforms: &U) {
+    surface.draw(&self.vertex_buffer, &self.index_buffer, program, uniforms,
+                 &Default::default()).unwrap();
+  }
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// A screen backed by OpenGL surface
+
+pub struct GLScreen {
+  screen: PixelScreen,
+  // Colors replacing the ones of the shaders
+  pub palette: Option<Palette>,
+  context: Rc<Context>,
+  // Shaders or presets to cycle through, and the one in use
+  shaders: Vec<String>,
+  current_shader: usize,
+  chain: Chain,
+  last_reload: Instant,
+  quad: Quad,
+  pixel_buffer: PixelBuffer<u8>,
+  texture: Texture2d,
+  past_textures: VecDeque<Texture2d>,
+  // What the chain draws to when the game takes only part of the frame
+  area_texture: Option<Texture2d>,
+  // Where the last repaint drew, if not to the whole frame
+  area: Option<Rect>,
+}
+
+impl GLScreen {
+  // `shader` is the name of a builtin shader, or the path to a GLSL file or
+  // to a preset
+  pub fn new<F: Facade>(display: &F, shader: &str) -> Self {
+    let context = display.get_context().clone();
+
+    // The requested shader comes first when cycling
+    let mut shaders = vec![shader.to_string()];
+    shaders.extend(shader::available(&context).into_iter()
+                   .filter(|&s| s != shader)
+                   .map(String::from));
+
+    let chain = Chain::load(&context, shader)
+      .unwrap_or_else(|e| panic!("Error loading shader {}", e));
 
     // The buffer to hold the pixel values
     let pixel_buffer = PixelBuffer::new_empty(display,
@@ -71,49 +115,93 @@ impl GLScreen {
       0..SCREEN_WIDTH as u32,
       0..SCREEN_HEIGHT as u32, 0..1);
 
-    // Only need past texture for the CRT shader
-    let past_textures;
-    if use_plain_shader {
-      past_textures = None;
+    let mut screen = GLScreen {
+      screen: PixelScreen::new(),
+      palette: None,
+      context,
+      shaders,
+      current_shader: 0,
+      chain,
+      last_reload: Instant::now(),
+      quad: Quad::new(display),
+      pixel_buffer,
+      texture,
+      past_textures: VecDeque::new(),
+      area_texture: None,
+      area: None,
+    };
+    screen.resize_history();
+    screen
+  }
+
+  // Keep as many past frames as the chain needs
+  fn resize_history(&mut self) {
+    let length = self.chain.history;
+    self.past_textures.truncate(length);
+    while self.past_textures.len() < length {
+      let tex = Texture2d::empty_with_format(&self.context,
+                                             UncompressedFloatFormat::U8,
+                                             MipmapsOption::NoMipmap,
+                                             64, 32).unwrap();
+
+      tex.main_level().raw_upload_from_pixel_buffer(
+        self.pixel_buffer.as_slice(),
+        0..SCREEN_WIDTH as u32,
+        0..SCREEN_HEIGHT as u32, 0..1);
+
+      self.past_textures.push_back(tex);
     }
-    else {
-      let mut texts = VecDeque::with_capacity(8);
-      for _ in 0..8 {
-        let tex = Texture2d::empty_with_format(display,
-                                               UncompressedFloatFormat::U8,
-                                               MipmapsOption::NoMipmap,
-                                               64, 32).unwrap();
+  }
 
-        tex.main_level().raw_upload_from_pixel_buffer(
-          pixel_buffer.as_slice(),
-          0..SCREEN_WIDTH as u32,
-          0..SCREEN_HEIGHT as u32, 0..1);
+  pub fn pixel_screen(&self) -> &PixelScreen {
+    &self.screen
+  }
+
+  pub fn shader_name(&self) -> &str {
+    self.chain.name()
+  }
+
+  pub fn chain_mut(&mut self) -> &mut Chain {
+    &mut self.chain
+  }
 
-        texts.push_front(tex);
+  // Switch to the next shader that loads
+  pub fn cycle_shader(&mut self) {
+    for _ in 0..self.shaders.len() {
+      self.current_shader = (self.current_shader + 1) % self.shaders.len();
+      match Chain::load(&self.context, &self.shaders[self.current_shader]) {
+        Ok(c) => {
+          self.chain = c;
+          self.resize_history();
+          status::post(format!("Using shader {}", self.shader_name()));
+          return
+        },
+        Err(e) => status::post(format!("Error loading shader {}", e)),
       }
-      past_textures = Some(texts);
     }
+  }
 
-    GLScreen {
-      screen: PixelScreen::new(),
-      program,
-      vertex_buffer,
-      index_buffer,
-      pixel_buffer,
-      texture,
-      past_textures,
-    }
+  // Part of the frame the screen was last drawn to, in pixels from the bottom
+  // left; None for all of it
+  pub fn area(&self) -> Option<Rect> {
+    self.area
   }
 
-  pub fn repaint<S: Surface>(&mut self, frame: &mut S) {
-    // If using the CRT shader, pop the oldest texture and push the previous one
-    if let Some(ref mut p_texts) = self.past_textures.as_mut() {
-      let tex = p_texts.pop_back().unwrap();
+  // Draw the screen to `area` of the frame, or to all of it
+  pub fn repaint<S: Surface>(&mut self, frame: &mut S, area: Option<Rect>) {
+    if self.last_reload.elapsed() >= RELOAD_INTERVAL {
+      self.chain.reload(&self.context);
+      self.last_reload = Instant::now();
+    }
+    self.resize_history();
+
+    // Pop the oldest texture and push the previous one
+    if let Some(tex) = self.past_textures.pop_back() {
       tex.main_level().raw_upload_from_pixel_buffer(
         self.pixel_buffer.as_slice(),
         0..SCREEN_WIDTH as u32,
         0..SCREEN_HEIGHT as u32, 0..1);
-      p_texts.push_front(tex);
+      self.past_textures.push_front(tex);
     }
 
     // Blit the logical screen to the pixel buffer, and then to the texture
@@ -126,53 +214,39 @@ impl GLScreen {
       0..SCREEN_WIDTH as u32,
       0..SCREEN_HEIGHT as u32, 0..1);
 
-    let dim = frame.get_dimensions();
-
-    // The uniforms are not the same for the CRT and plain shaders.  We cannot
-    // make a single frame.draw call, since the uniform! macro return different
-    // types in the two branches.
-    if let Some(ref p_texts) = self.past_textures.as_ref() {
-      let uniforms = uniform! {
-        iResolution: (dim.0 as f32, dim.1 as f32),
-        tex: self.texture.sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev0_tex: p_texts[0].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev1_tex: p_texts[1].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev2_tex: p_texts[2].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev3_tex: p_texts[3].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev4_tex: p_texts[4].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev5_tex: p_texts[5].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-        prev6_tex: p_texts[6].sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-      };
-
-      frame.draw(&self.vertex_buffer, &self.index_buffer, &self.program,
-                 &uniforms, &Default::default()).unwrap();
-    } else {
-      let uniforms = uniform! {
-        tex: self.texture.sampled()
-          .minify_filter(MinifySamplerFilter::Nearest)
-          .magnify_filter(MagnifySamplerFilter::Nearest),
-      };
-
-      frame.draw(&self.vertex_buffer, &self.index_buffer, &self.program,
-                 &uniforms, &Default::default()).unwrap();
+    self.area = area;
+    let r = match area {
+      Some(r) => r,
+      None => {
+        self.chain.draw(&self.context, frame, &self.quad, &self.texture,
+                        &self.past_textures, self.palette);
+        return
+      },
+    };
+
+    // Shaders use gl_FragCoord, so they draw to a texture of the size of the
+    // area, which is then copied in place
+    if self.area_texture.as_ref().map(|t| t.dimensions())
+      != Some((r.width, r.height)) {
+      self.area_texture = Some(Texture2d::empty_with_format(
+        &self.context, UncompressedFloatFormat::U8U8U8U8,
+        MipmapsOption::NoMipmap, r.width, r.height).unwrap());
     }
+    let target = self.area_texture.as_ref().unwrap();
+    let mut fb = SimpleFrameBuffer::new(&self.context, target).unwrap();
+    self.chain.draw(&self.context, &mut fb, &self.quad, &self.texture,
+                    &self.past_textures, self.palette);
 
+    let source = Rect { left: 0, bottom: 0, width: r.width, height: r.height };
+    let dest = BlitTarget {
+      left: r.left,
+      bottom: r.bottom,
+      width: r.width as i32,
+      height: r.height as i32,
+    };
+    frame.clear_color(0.0, 0.0, 0.0, 1.0);
+    frame.blit_from_simple_framebuffer(&fb, &source, &dest,
+                                       MagnifySamplerFilter::Nearest);
   }
 }
 
@@ -181,7 +255,12 @@ impl Screen for GLScreen {
     self.screen.clear();
   }
 
-  fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> u8 {
-    self.screen.draw_sprite(x, y, sprite)
+  fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8],
+                 clip: bool) -> Collision {
+    self.screen.draw_sprite(x, y, sprite, clip)
+  }
+
+  fn trace_draw(&mut self, origin: DrawOrigin) {
+    self.screen.trace_draw(origin);
   }
 }
@@ -0,0 +1,212 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use glium::{Api, Program, Version};
+use glium::backend::Context;
+
+const VERTEX_MODERN: &str = include_str!("shaders/vertex.glsl");
+const VERTEX_LEGACY: &str = include_str!("shaders/vertex-120.glsl");
+
+// Added to every fragment shader, so that they all follow the chosen palette
+const PALETTE: &str = "\
+// Colors replacing the ones of the shader, if use_palette is set
+uniform bool use_palette;
+uniform vec3 palette0;
+uniform vec3 palette1;
+uniform vec3 palette2;
+uniform vec3 palette3;
+
+// Color of a value of the logical screen
+vec3 pixel_color(float value, vec3 background, vec3 foreground) {
+  int p = int(value * 255.0 + 0.5);
+  if (!use_palette) return p == 0 ? background : foreground;
+  if (p == 1) return palette1;
+  if (p == 2) return palette2;
+  if (p == 3) return palette3;
+  return palette0;
+}
+";
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Fragment shaders for the screen, bundled or loaded from disk
+
+pub struct Builtin {
+  pub name: &'static str,
+  // GLSL 1.50 and 1.20 versions, when available
+  modern: Option<&'static str>,
+  legacy: Option<&'static str>,
+  // Uniform parameters: name, default, min and max
+  pub parameters: &'static [(&'static str, f32, f32, f32)],
+}
+
+const CRT_PARAMETERS: [(&str, f32, f32, f32); 5] = [
+  ("hardScan", -8.0, -16.0, -2.0),
+  ("hardPix", -3.0, -4.0, -2.0),
+  ("curvature", 1.0, 0.0, 5.0),
+  ("maskDark", 0.5, 0.0, 1.0),
+  ("maskLight", 1.5, 1.0, 2.0),
+];
+
+const CRT_PHOSPHOR_PARAMETERS: [(&str, f32, f32, f32); 6] = [
+  ("response_time", 0.1, 0.0, 1.0),
+  CRT_PARAMETERS[0],
+  CRT_PARAMETERS[1],
+  CRT_PARAMETERS[2],
+  CRT_PARAMETERS[3],
+  CRT_PARAMETERS[4],
+];
+
+pub static BUILTINS: [Builtin; 4] = [
+  Builtin {
+    name: "plain",
+    modern: Some(include_str!("shaders/fragment.glsl")),
+    legacy: Some(include_str!("shaders/fragment-120.glsl")),
+    parameters: &[],
+  },
+  Builtin {
+    name: "crt-phosphor",
+    modern: Some(include_str!("shaders/crt-phosphor.glsl")),
+    legacy: Some(include_str!("shaders/crt-phosphor-120.glsl")),
+    parameters: &CRT_PHOSPHOR_PARAMETERS,
+  },
+  Builtin {
+    name: "crt-lottes",
+    modern: Some(include_str!("shaders/crt-lottes.glsl")),
+    legacy: None,
+    parameters: &CRT_PARAMETERS,
+  },
+  Builtin {
+    name: "phosphor-trail",
+    modern: Some(include_str!("shaders/phosphor-trail-hunterk.glsl")),
+    legacy: None,
+    parameters: &[("response_time", 1.0, 0.0, 1.0)],
+  },
+];
+
+pub enum Source {
+  Builtin(&'static Builtin),
+  // Reloaded when the modification time changes
+  File { path: PathBuf, modified: Option<SystemTime> },
+}
+
+impl Source {
+  // Builtin names take precedence over paths
+  pub fn parse(name: &str) -> Self {
+    match BUILTINS.iter().find(|b| b.name == name) {
+      Some(b) => Source::Builtin(b),
+      None => Source::File { path: PathBuf::from(name), modified: None },
+    }
+  }
+
+  pub fn name(&self) -> String {
+    match self {
+      Source::Builtin(b) => b.name.to_string(),
+      Source::File { path, .. } => path.display().to_string(),
+    }
+  }
+
+  // Whether the file changed on disk since the last compilation
+  pub fn changed(&self) -> bool {
+    match self {
+      Source::Builtin(_) => false,
+      Source::File { path, modified } => mtime(path) != *modified,
+    }
+  }
+
+  pub fn compile(&mut self, context: &Rc<Context>) -> Result<Program, String> {
+    let modern = supports_modern(context);
+
+    let fragment = match self {
+      Source::Builtin(b) => {
+        let f = if modern { b.modern.or(b.legacy) } else { b.legacy };
+        f.ok_or_else(|| format!("shader '{}' needs GLSL 1.50", b.name))?
+          .to_string()
+      },
+      Source::File { path, modified } => {
+        *modified = mtime(path);
+        fs::read_to_string(&path)
+          .map_err(|e| format!("{}: {}", path.display(), e))?
+      },
+    };
+
+    let vertex = if glsl_version(&fragment) >= 130 { VERTEX_MODERN }
+                 else { VERTEX_LEGACY };
+    let fragment = with_palette(&fragment);
+
+    Program::from_source(context, vertex, &fragment, None)
+      .map_err(|e| format!("{}: {}", self.name(), e))
+  }
+}
+
+// Names of all the builtins this context can run
+pub fn available(context: &Context) -> Vec<&'static str> {
+  let modern = supports_modern(context);
+  BUILTINS.iter()
+    .filter(|b| b.legacy.is_some() || modern)
+    .map(|b| b.name)
+    .collect()
+}
+
+pub fn supports_modern(context: &Context) -> bool {
+  context.is_glsl_version_supported(&Version(Api::Gl, 1, 50))
+}
+
+pub fn mtime(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Version number from the #version directive, 110 if there is none
+fn glsl_version(source: &str) -> u32 {
+  source.lines()
+    .filter_map(|l| l.trim().strip_prefix("#version"))
+    .filter_map(|v| v.split_whitespace().next())
+    .filter_map(|v| v.parse().ok())
+    .next()
+    .unwrap_or(110)
+}
+
+// Insert the palette code after the #version directive, which must come first
+fn with_palette(source: &str) -> String {
+  let mut end = 0;
+  for line in source.split_inclusive('\n') {
+    end += line.len();
+    if line.trim().starts_with("#version") {
+      let (head, tail) = source.split_at(end);
+      let newline = if head.ends_with('\n') { "" } else { "\n" };
+      return format!("{}{}{}{}", head, newline, PALETTE, tail)
+    }
+  }
+  format!("{}{}", PALETTE, source)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_glsl_version() {
+    assert_eq!(glsl_version(BUILTINS[1].modern.unwrap()), 150);
+    assert_eq!(glsl_version(BUILTINS[1].legacy.unwrap()), 120);
+    assert_eq!(glsl_version("void main() {}"), 110);
+  }
+
+  #[test]
+  fn adds_palette_after_version() {
+    let source = with_palette("// CRT\n#version 150\nvoid main() {}\n");
+    assert!(source.starts_with("// CRT\n#version 150\n// Colors replacing"));
+    assert!(source.ends_with("return palette0;\n}\nvoid main() {}\n"));
+    assert_eq!(glsl_version(&source), 150);
+
+    assert!(with_palette("void main() {}").starts_with(PALETTE));
+    assert!(with_palette("#version 120").starts_with("#version 120\n//"));
+  }
+
+  #[test]
+  fn parses_builtin_names_before_paths() {
+    assert_eq!(Source::parse("crt-lottes").name(), "crt-lottes");
+    assert!(!Source::parse("crt-lottes").changed());
+    assert_eq!(Source::parse("shaders/mine.glsl").name(), "shaders/mine.glsl");
+  }
+}
@@ -32,21 +32,22 @@ vec2 res=iResolution.xy / 6.0;
 // Hardness of scanline.
 //  -8.0 = soft
 // -16.0 = medium
-float hardScan=-8.0;
+uniform float hardScan;
 
 // Hardness of pixels in scanline.
 // -2.0 = soft
 // -4.0 = hard
-float hardPix=-3.0;
+uniform float hardPix;
 
 // Display warp.
 // 0.0 = none
-// 1.0/8.0 = extreme
+// 5.0 = extreme
+uniform float curvature;
 vec2 warp=vec2(1.0/40.0,1.0/24.0);
 
 // Amount of shadow mask.
-float maskDark=0.5;
-float maskLight=1.5;
+uniform float maskDark;
+uniform float maskLight;
 
 //------------------------------------------------------------------------
 
@@ -65,7 +66,7 @@ vec3 ToSrgb(vec3 c){return vec3(ToSrgb1(c.r),ToSrgb1(c.g),ToSrgb1(c.b));}
 vec3 Fetch(vec2 pos,vec2 off){
   pos=floor(pos*res+off)/res;
   if(max(abs(pos.x-0.5),abs(pos.y-0.5))>0.5)return vec3(0.0,0.0,0.0);
-  return ToLinear(texture2D(tex,pos.xy,-16.0).r * vec3(200, 210, 245) + vec3(0.022, 0.027, 0.03));}
+  return ToLinear(pixel_color(texture2D(tex,pos.xy,-16.0).r,vec3(0.0),vec3(200,210,245)/255.0) + vec3(0.022, 0.027, 0.03));}
 
 // Distance in emulated pixels to nearest texel.
 vec2 Dist(vec2 pos){pos=pos*res;return -((pos-floor(pos))-vec2(0.5));}
@@ -123,7 +124,7 @@ vec3 Tri(vec2 pos){
 // Distortion of scanlines, and end of screen alpha.
 vec2 Warp(vec2 pos){
   pos=pos*2.0-1.0;
-  pos*=vec2(1.0+(pos.y*pos.y)*warp.x,1.0+(pos.x*pos.x)*warp.y);
+  pos*=vec2(1.0+(pos.y*pos.y)*warp.x*curvature,1.0+(pos.x*pos.x)*warp.y*curvature);
   return pos*0.5+0.5;}
 
 // Shadow mask.
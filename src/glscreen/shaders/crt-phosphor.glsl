@@ -20,7 +20,7 @@ uniform sampler2D prev6_tex;
 
 // This controls how brightly the phosphors glow. The default value is 1, lower
 // values reduce the effect.
-float response_time = 0.1;
+uniform float response_time;
 
 // Emulated input resolution.
 #if 0
@@ -34,21 +34,22 @@ vec2 res=iResolution.xy / 6.0;
 // Hardness of scanline.
 //  -8.0 = soft
 // -16.0 = medium
-float hardScan=-8.0;
+uniform float hardScan;
 
 // Hardness of pixels in scanline.
 // -2.0 = soft
 // -4.0 = hard
-float hardPix=-3.0;
+uniform float hardPix;
 
 // Display warp.
 // 0.0 = none
-// 1.0/8.0 = extreme
+// 5.0 = extreme
+uniform float curvature;
 vec2 warp=vec2(1.0/40.0,1.0/24.0);
 
 // Amount of shadow mask.
-float maskDark=0.5;
-float maskLight=1.5;
+uniform float maskDark;
+uniform float maskLight;
 
 
 //------------------------------------------------------------------------
@@ -59,7 +60,8 @@ mat3 RGB_to_YIQ = mat3(0.299, 0.587, 0.114,
                        0.211456, -0.522591, 0.311135);
 
 vec3 fetch(sampler2D text, vec2 pos) {
-  return texture2D(text, pos.xy).r * vec3(200, 210, 245);
+  return pixel_color(texture2D(text, pos.xy).r,
+                     vec3(0.0), vec3(200, 210, 245) / 255.0);
 }
 
 vec3 main_phosphor(vec2 pos) {
@@ -174,7 +176,7 @@ vec3 Tri(vec2 pos){
 // Distortion of scanlines, and end of screen alpha.
 vec2 Warp(vec2 pos){
   pos=pos*2.0-1.0;
-  pos*=vec2(1.0+(pos.y*pos.y)*warp.x,1.0+(pos.x*pos.x)*warp.y);
+  pos*=vec2(1.0+(pos.y*pos.y)*warp.x*curvature,1.0+(pos.x*pos.x)*warp.y*curvature);
   return pos*0.5+0.5;}
 
 // Shadow mask.
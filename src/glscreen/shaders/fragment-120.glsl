@@ -5,5 +5,6 @@ varying vec2 v_tex_coords;
 uniform sampler2D tex;
 
 void main() {
-  gl_FragColor = vec4(texture2D(tex, v_tex_coords).x * 255, 0.0, 1.0, 1.0);
+  gl_FragColor = vec4(pixel_color(texture2D(tex, v_tex_coords).x,
+                          vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 1.0)), 1.0);
 }
@@ -6,5 +6,6 @@ out vec4 color;
 uniform sampler2D tex;
 
 void main() {
-  color = vec4(texture(tex, v_tex_coords).x * 255, 0.0, 1.0, 1.0);
+  color = vec4(pixel_color(texture(tex, v_tex_coords).x,
+                          vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 1.0)), 1.0);
 }
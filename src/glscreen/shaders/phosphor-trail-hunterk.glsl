@@ -7,7 +7,7 @@
 
 // This controls how brightly the phosphors glow. The default value is 1, lower
 // values reduce the effect.
-float response_time = 1.0;
+uniform float response_time;
 
 mat3 RGB_to_YIQ = mat3(0.299, 0.587, 0.114,
                        0.595716, -0.274453, -0.321263,
@@ -26,7 +26,8 @@ uniform sampler2D prev5_tex;
 uniform sampler2D prev6_tex;
 
 vec3 fetch(sampler2D text) {
-  return texture2D(text, v_tex_coords).r * vec3(200, 210, 245);
+  return pixel_color(texture2D(text, v_tex_coords).r,
+                     vec3(0.0), vec3(200, 210, 245) / 255.0);
 }
 
 void main() {
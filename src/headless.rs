@@ -0,0 +1,21 @@
+use crate::capture::Capture;
+use crate::chip8::keyboard::SimpleKeyboard;
+use crate::chip8::screen::PixelScreen;
+use crate::{Args, Machine, palette};
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Run a fixed number of frames as fast as possible, without display or input
+
+pub fn run(args: &Args, chip8: &mut Machine) {
+  let mut capture = Capture::new(args, palette(args).unwrap_or_default());
+  let mut screen = PixelScreen::new();
+  let mut keyboard = SimpleKeyboard::new();
+  let frame_ms = 1000.0 / args.flag_fps.max(1) as f32;
+
+  for _ in 0..args.flag_frames {
+    chip8.run(frame_ms, &mut screen, &mut keyboard);
+    capture.frame(&screen);
+  }
+
+  capture.finish(&screen);
+}
@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::romdb::RomInfo;
+
+// Hex keys by position on the COSMAC VIP keypad
+pub const KEYPAD: [[u8; 4]; 4] = [
+  [0x1, 0x2, 0x3, 0xC],
+  [0x4, 0x5, 0x6, 0xD],
+  [0x7, 0x8, 0x9, 0xE],
+  [0xA, 0x0, 0xB, 0xF],
+];
+
+// Host keys at the same positions as the keypad
+pub const LAYOUTS: [(&str, [[&str; 4]; 4]); 5] = [
+  ("colemak", [["1", "2", "3", "4"],
+               ["Q", "W", "F", "P"],
+               ["A", "R", "S", "T"],
+               ["Z", "X", "C", "V"]]),
+  ("qwerty", [["1", "2", "3", "4"],
+              ["Q", "W", "E", "R"],
+              ["A", "S", "D", "F"],
+              ["Z", "X", "C", "V"]]),
+  ("azerty", [["1", "2", "3", "4"],
+              ["A", "Z", "E", "R"],
+              ["Q", "S", "D", "F"],
+              ["W", "X", "C", "V"]]),
+  ("dvorak", [["1", "2", "3", "4"],
+              ["Apostrophe", "Comma", "Period", "P"],
+              ["A", "O", "E", "U"],
+              ["Semicolon", "Q", "J", "K"]]),
+  ("numpad", [["Numpad7", "Numpad8", "Numpad9", "NumpadDivide"],
+              ["Numpad4", "Numpad5", "Numpad6", "NumpadMultiply"],
+              ["Numpad1", "Numpad2", "Numpad3", "NumpadSubtract"],
+              ["Numpad0", "NumpadDecimal", "NumpadEnter", "NumpadAdd"]]),
+];
+
+// Names front-ends use for the same key
+const ALIASES: [(&str, &str); 11] = [
+  ("numpadslash", "numpaddivide"),
+  ("divide", "numpaddivide"),
+  ("numpadasterisk", "numpadmultiply"),
+  ("multiply", "numpadmultiply"),
+  ("numpadminus", "numpadsubtract"),
+  ("subtract", "numpadsubtract"),
+  ("numpadplus", "numpadadd"),
+  ("add", "numpadadd"),
+  ("numpaddot", "numpaddecimal"),
+  ("decimal", "numpaddecimal"),
+  ("return", "enter"),
+];
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Mapping from host keys to the 16 hex keys
+
+// Host keys are named like the Debug output of the key codes of winit or
+// minifb ("Key1", "Q", "Numpad7"...), ignoring case.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keymap {
+  pub bindings: [Vec<String>; 16],
+}
+
+impl Keymap {
+  pub fn layout(name: &str) -> Option<Self> {
+    let (_, rows) = LAYOUTS.iter().find(|l| l.0 == name)?;
+
+    let mut keymap = Keymap { bindings: Default::default() };
+    for (keys, hosts) in KEYPAD.iter().zip(rows.iter()) {
+      for (&k, &host) in keys.iter().zip(hosts.iter()) {
+        keymap.bindings[k as usize].push(host.to_string());
+      }
+    }
+    Some(keymap)
+  }
+
+  // Hex key bound to a host key
+  pub fn key(&self, host: &str) -> Option<u8> {
+    let host = normalize(host);
+    self.bindings.iter()
+      .position(|hosts| hosts.iter().any(|h| normalize(h) == host))
+      .map(|k| k as u8)
+  }
+
+  // Add a host key to a hex key, removing it from any other
+  pub fn bind(&mut self, key: u8, host: &str) {
+    let normalized = normalize(host);
+    for hosts in &mut self.bindings {
+      hosts.retain(|h| normalize(h) != normalized);
+    }
+    self.bindings[key as usize].push(host.to_string());
+  }
+
+  pub fn clear(&mut self, key: u8) {
+    self.bindings[key as usize].clear();
+  }
+
+  // Apply the layout, then the bindings, of a section of the file
+  fn apply(&mut self, section: &KeymapSection) -> Result<(), String> {
+    if let Some(ref name) = section.layout {
+      *self = Keymap::layout(name)
+        .ok_or_else(|| format!("unknown layout '{}'", name))?;
+    }
+
+    for (key, hosts) in &section.keys {
+      let k = u8::from_str_radix(key, 16).ok()
+        .filter(|&k| k < 16)
+        .ok_or_else(|| format!("invalid key '{}'", key))?;
+      self.bindings[k as usize] = hosts.clone();
+    }
+    Ok(())
+  }
+
+  // Keymap from the default layout, keymap.toml, the layout and keys of the
+  // ROM database, the overrides of the ROM, and the layout given on the
+  // command line
+  pub fn load(rom: &str, default_layout: Option<&str>, info: &RomInfo,
+              layout: Option<&str>) -> Result<Self, String> {
+    let mut file = KeymapFile::load();
+    if file.global.layout.is_none() {
+      file.global.layout = default_layout.map(String::from);
+    }
+    file.keymap(rom_name(rom), info, layout)
+  }
+
+  // Write the bindings to keymap.toml, for all ROMs or only for one
+  pub fn save(&self, rom: Option<&str>) -> io::Result<()> {
+    let mut file = KeymapFile::load();
+    let section = KeymapSection {
+      layout: None,
+      keys: (0..16)
+        .map(|k| (format!("{:X}", k), self.bindings[k].clone()))
+        .collect(),
+    };
+
+    match rom {
+      Some(rom) => { file.roms.insert(rom_name(rom).to_string(), section); },
+      None => file.global = section,
+    }
+    config::save(KEYMAP_FILE, &file)
+  }
+}
+
+impl Default for Keymap {
+  fn default() -> Self {
+    Keymap::layout(LAYOUTS[0].0).unwrap()
+  }
+}
+
+// Lowercase, and with a single name for keys front-ends name differently
+fn normalize(host: &str) -> String {
+  let mut name = host.to_lowercase().replace('_', "");
+  if name.len() == 4 && name.starts_with("key") {
+    name = name[3..].to_string();
+  }
+  match ALIASES.iter().find(|a| a.0 == name) {
+    Some(a) => a.1.to_string(),
+    None => name,
+  }
+}
+
+// Name of a key typed as a character in the terminal
+pub fn char_name(c: char) -> String {
+  match c {
+    ' ' => "Space".to_string(),
+    '\'' => "Apostrophe".to_string(),
+    ',' => "Comma".to_string(),
+    '.' => "Period".to_string(),
+    ';' => "Semicolon".to_string(),
+    '/' => "Slash".to_string(),
+    '-' => "Minus".to_string(),
+    '=' => "Equals".to_string(),
+    c => c.to_uppercase().to_string(),
+  }
+}
+
+pub fn rom_name(rom: &str) -> &str {
+  Path::new(rom).file_name()
+    .and_then(|n| n.to_str())
+    .unwrap_or(rom)
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// keymap.toml in the configuration directory:
+//
+//   layout = "qwerty"
+//
+//   [keys]
+//   5 = ["W", "Up"]
+//
+//   [roms."pong.ch8".keys]
+//   1 = ["Up"]
+//   4 = ["Down"]
+//
+// Keys listed replace the bindings of the layout.  ROMs are matched by file
+// name.
+
+const KEYMAP_FILE: &str = "keymap.toml";
+
+#[derive(Deserialize, Serialize, Default)]
+struct KeymapSection {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  layout: Option<String>,
+  #[serde(default)]
+  keys: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+struct KeymapFile {
+  #[serde(flatten)]
+  global: KeymapSection,
+  #[serde(default)]
+  roms: BTreeMap<String, KeymapSection>,
+}
+
+impl KeymapFile {
+  fn load() -> Self {
+    config::load(KEYMAP_FILE)
+  }
+
+  fn keymap(&self, rom: &str, info: &RomInfo,
+            layout: Option<&str>) -> Result<Keymap, String> {
+    let mut keymap = Keymap::default();
+    keymap.apply(&self.global)?;
+    keymap.apply(&KeymapSection {
+      layout: info.layout.clone(),
+      keys: info.keys.clone(),
+    })?;
+    if let Some(section) = self.roms.get(rom) {
+      keymap.apply(section)?;
+    }
+    if let Some(name) = layout {
+      keymap.apply(&KeymapSection {
+        layout: Some(name.to_string()),
+        keys: BTreeMap::new(),
+      })?;
+    }
+    Ok(keymap)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn normalizes_front_end_names() {
+    let keymap = Keymap::layout("numpad").unwrap();
+    assert_eq!(keymap.key("Numpad7"), Some(0x1));
+    assert_eq!(keymap.key("NumPad7"), Some(0x1));
+    assert_eq!(keymap.key("NumPadSlash"), Some(0xC));
+    assert_eq!(keymap.key("Divide"), Some(0xC));
+    assert_eq!(keymap.key("Return"), None);
+    assert_eq!(keymap.key("NumpadEnter"), Some(0xB));
+
+    let keymap = Keymap::default();
+    assert_eq!(keymap.key("Key1"), Some(0x1));
+    assert_eq!(keymap.key(&char_name('p')), Some(0xD));
+    assert_eq!(keymap.key("Key5"), None);
+  }
+
+  #[test]
+  fn binding_moves_host_keys() {
+    let mut keymap = Keymap::default();
+    keymap.bind(0x5, "Up");
+    keymap.bind(0x5, "Q");
+    assert_eq!(keymap.key("Up"), Some(0x5));
+    assert_eq!(keymap.key("Q"), Some(0x5));
+    assert_eq!(keymap.bindings[0x4], Vec::<String>::new());
+    assert_eq!(keymap.bindings[0x5], vec!["W", "Up", "Q"]);
+  }
+
+  #[test]
+  fn applies_overrides_in_order() {
+    let file: KeymapFile = toml::from_str(r#"
+      layout = "qwerty"
+
+      [keys]
+      5 = ["W", "Up"]
+
+      [roms."pong.ch8"]
+      keys = { 1 = ["Up"], 4 = ["Down"] }
+    "#).unwrap();
+    let none = RomInfo::default();
+
+    let keymap = file.keymap("tetris.ch8", &none, None).unwrap();
+    assert_eq!(keymap.key("E"), Some(0x6));
+    assert_eq!(keymap.key("Up"), Some(0x5));
+
+    let keymap = file.keymap("pong.ch8", &none, None).unwrap();
+    assert_eq!(keymap.key("Up"), Some(0x1));
+    assert_eq!(keymap.key("Down"), Some(0x4));
+
+    let keymap = file.keymap("pong.ch8", &none, Some("azerty")).unwrap();
+    assert_eq!(keymap.key("Z"), Some(0x5));
+    assert!(file.keymap("pong.ch8", &none, Some("bepo")).is_err());
+
+    // The ROM database comes before the file for the ROM
+    let info = RomInfo {
+      layout: Some("azerty".to_string()),
+      keys: vec![("4".to_string(), vec!["Up".to_string()]),
+                 ("6".to_string(), vec!["Space".to_string()])]
+        .into_iter().collect(),
+      ..RomInfo::default()
+    };
+    let keymap = file.keymap("tetris.ch8", &info, None).unwrap();
+    assert_eq!(keymap.key("Z"), Some(0x5));
+    assert_eq!(keymap.key("Up"), Some(0x4));
+    assert_eq!(keymap.key("Space"), Some(0x6));
+    let keymap = file.keymap("pong.ch8", &info, None).unwrap();
+    assert_eq!(keymap.key("Up"), Some(0x1));
+    assert_eq!(keymap.key("Space"), Some(0x6));
+    let keymap = file.keymap("pong.ch8", &info, Some("qwerty")).unwrap();
+    assert_eq!(keymap.key("W"), Some(0x5));
+  }
+
+  #[test]
+  fn round_trips_through_toml() {
+    let mut file = KeymapFile::default();
+    file.global.layout = Some("dvorak".to_string());
+    file.roms.insert("pong.ch8".to_string(), KeymapSection {
+      layout: None,
+      keys: vec![("1".to_string(), vec!["Up".to_string()])]
+        .into_iter().collect(),
+    });
+
+    let none = RomInfo::default();
+    let text = toml::to_string(&file).unwrap();
+    let read: KeymapFile = toml::from_str(&text).unwrap();
+    assert_eq!(read.keymap("pong.ch8", &none, None).unwrap(),
+               file.keymap("pong.ch8", &none, None).unwrap());
+  }
+}
@@ -0,0 +1,91 @@
+use imgui::{Ui, ImStr, im_str};
+
+use crate::keymap::{Keymap, KEYPAD, LAYOUTS};
+
+pub struct KeymapView {
+  open: bool,
+  // Hex key waiting for a host key to bind
+  capturing: Option<u8>,
+  // Save the bindings for the current ROM only
+  per_rom: bool,
+  status: String,
+}
+
+impl KeymapView {
+  pub fn new() -> KeymapView {
+    KeymapView {
+      open: true,
+      capturing: None,
+      per_rom: false,
+      status: String::new(),
+    }
+  }
+
+  // Bind a pressed host key to the hex key being edited, if any.  Returns
+  // whether the key was used.
+  pub fn capture(&mut self, host: &str, keymap: &mut Keymap) -> bool {
+    match self.capturing.take() {
+      Some(k) => {
+        if host != "Escape" {
+          keymap.bind(k, host);
+        }
+        true
+      },
+      None => false,
+    }
+  }
+
+  pub fn draw(&mut self, ui: &Ui, title: &ImStr, keymap: &mut Keymap,
+              rom: &str) {
+    let capturing = &mut self.capturing;
+    let per_rom = &mut self.per_rom;
+    let status = &mut self.status;
+
+    ui.window(title)
+      .opened(&mut self.open)
+      .build(|| {
+        ui.text(im_str!("layout:"));
+        for (name, _) in LAYOUTS.iter() {
+          ui.same_line(0.0);
+          if ui.small_button(&im_str!("{}", name)) {
+            *keymap = Keymap::layout(name).unwrap();
+          }
+        }
+
+        for row in KEYPAD.iter() {
+          for (i, &k) in row.iter().enumerate() {
+            if i > 0 {
+              ui.same_line(0.0);
+            }
+            let hosts = if *capturing == Some(k) { "...".to_string() }
+                        else { keymap.bindings[k as usize].join(" ") };
+            if ui.button(&im_str!("{:X}: {}##{}", k, hosts, k), [120.0, 0.0]) {
+              *capturing = Some(k);
+            }
+          }
+        }
+
+        match *capturing {
+          Some(k) => {
+            ui.text(im_str!("press a key to bind to {:X}, Escape to cancel", k));
+            if ui.small_button(im_str!("clear")) {
+              keymap.clear(k);
+              *capturing = None;
+            }
+          },
+          None => ui.text(im_str!("click a key to add a binding")),
+        }
+
+        ui.separator();
+        ui.checkbox(im_str!("only for this ROM"), per_rom);
+        if ui.small_button(im_str!("save")) {
+          *status = match keymap.save(if *per_rom { Some(rom) } else { None }) {
+            Ok(()) => "saved".to_string(),
+            Err(e) => format!("error: {}", e),
+          };
+        }
+        ui.same_line(0.0);
+        ui.text(im_str!("{}", status));
+      });
+  }
+}
@@ -0,0 +1,82 @@
+use imgui::{Ui, ImStr, StyleColor, im_str};
+
+use crate::chip8::Keyboard;
+use crate::chip8::keyboard::{KeySource, SimpleKeyboard};
+use crate::keymap::{Keymap, KEYPAD};
+
+const KEY_SIZE: f32 = 48.0;
+const PRESSED_COLOR: [f32; 4] = [0.9, 0.6, 0.1, 1.0];
+
+pub struct KeypadView {
+  pub open: bool,
+  // Hex key held down with the mouse
+  clicked: Option<u8>,
+}
+
+impl KeypadView {
+  pub fn new(open: bool) -> KeypadView {
+    KeypadView {
+      open,
+      clicked: None,
+    }
+  }
+
+  pub fn toggle(&mut self) {
+    self.open = !self.open;
+  }
+
+  // Still drawn for one frame after closing, to release the clicked key
+  pub fn visible(&self) -> bool {
+    self.open || self.clicked.is_some()
+  }
+
+  // Draw the keypad with the host keys bound to each key.  Keys stay pressed
+  // while the mouse button is held on them.
+  pub fn draw(&mut self, ui: &Ui, title: &ImStr,
+              keyboard: &mut SimpleKeyboard, keymap: &Keymap) {
+    let mut held = None;
+
+    if self.open {
+      ui.window(title)
+        .always_auto_resize(true)
+        .collapsible(false)
+        .opened(&mut self.open)
+        .build(|| {
+          for row in KEYPAD.iter() {
+            for (i, &k) in row.iter().enumerate() {
+              if i > 0 {
+                ui.same_line(0.0);
+              }
+
+              let _color = if keyboard.is_pressed(k) {
+                Some(ui.push_style_colors(&[
+                  (StyleColor::Button, PRESSED_COLOR),
+                  (StyleColor::ButtonHovered, PRESSED_COLOR),
+                  (StyleColor::ButtonActive, PRESSED_COLOR),
+                ]))
+              } else { None };
+
+              let host = keymap.bindings[k as usize].first()
+                .map(String::as_str)
+                .unwrap_or("");
+              ui.button(&im_str!("{:X}\n{}##{}", k, host, k),
+                        [KEY_SIZE, KEY_SIZE]);
+              if ui.is_item_active() {
+                held = Some(k);
+              }
+            }
+          }
+        });
+    }
+
+    if held != self.clicked {
+      if let Some(k) = self.clicked {
+        keyboard.release_key_from(KeySource::Keypad, k);
+      }
+      if let Some(k) = held {
+        keyboard.press_key_from(KeySource::Keypad, k);
+      }
+      self.clicked = held;
+    }
+  }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use crate::cart;
+use crate::romdb::{RomDb, RomInfo};
+
+// Extensions of the files that can be loaded
+pub const EXTENSIONS: [&str; 6] = ["ch8", "c8", "rom", "gif", "hex", "zip"];
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// ROM files, recognized by their contents:
+//
+//   - Octo cartridges (GIF), with their settings
+//   - zip archives holding a single ROM
+//   - raw bytes otherwise
+//
+// except for .hex files, which are hex dumps, e.g. "00e0 a22a" or "0x00 0xE0",
+// where "#" and ";" start comments and "0200:" addresses are skipped.  Plenty
+// of raw ROMs are also valid hex, so the contents alone can't tell.
+
+pub struct Rom {
+  pub program: Vec<u8>,
+  // Settings stored along with the program
+  pub embedded: Option<RomInfo>,
+}
+
+impl Rom {
+  // The entry of the database, or the embedded settings
+  pub fn info(&self, db: &RomDb) -> Option<RomInfo> {
+    db.get(&self.program).cloned().or_else(|| self.embedded.clone())
+  }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Rom> {
+  decode(path.as_ref(), fs::read(&path)?)
+}
+
+// The ROM in `data`, read from a file named `path`
+pub fn decode(path: &Path, data: Vec<u8>) -> io::Result<Rom> {
+  if has_extension(path, "hex") {
+    match parse_hex(&data) {
+      Some(program) => Ok(Rom { program, embedded: None }),
+      None => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                 "not a hex dump")),
+    }
+  } else if data.starts_with(b"GIF8") {
+    let cart = cart::decode(&data)?;
+    Ok(Rom { program: cart.rom()?, embedded: Some(cart.info()) })
+  } else if data.starts_with(b"PK\x03\x04") {
+    unzip(data)
+  } else {
+    Ok(Rom { program: data, embedded: None })
+  }
+}
+
+// The only ROM in the archive.  When there are other files, only the ones
+// with a ROM extension count.
+fn unzip(data: Vec<u8>) -> io::Result<Rom> {
+  let mut zip = zip::ZipArchive::new(Cursor::new(data))?;
+  let files: Vec<(usize, String)> = (0..zip.len())
+    .filter_map(|i| {
+      let f = zip.by_index(i).ok()?;
+      let name = f.name().to_string();
+      let base = name.rsplit('/').next().unwrap_or("");
+      if f.is_dir() || base.starts_with('.') || name.starts_with("__MACOSX/") {
+        None
+      } else {
+        Some((i, name))
+      }
+    })
+    .collect();
+
+  let roms: Vec<&(usize, String)> = if files.len() > 1 {
+    files.iter().filter(|(_, name)| is_rom_name(Path::new(name))).collect()
+  } else {
+    files.iter().collect()
+  };
+  match roms[..] {
+    [(i, name)] => {
+      let mut buf = Vec::new();
+      zip.by_index(*i)?.read_to_end(&mut buf)?;
+      decode(Path::new(name), buf)
+    },
+    _ => Err(io::Error::new(io::ErrorKind::InvalidData,
+                            format!("expected one ROM in the archive, found {}",
+                                    roms.len()))),
+  }
+}
+
+pub fn is_rom_name(path: &Path) -> bool {
+  EXTENSIONS.iter().any(|e| has_extension(path, e))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+  path.extension()
+    .and_then(|e| e.to_str())
+    .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+// Bytes of a hex dump, if `data` is one
+fn parse_hex(data: &[u8]) -> Option<Vec<u8>> {
+  let text = std::str::from_utf8(data).ok()?;
+  let mut bytes = Vec::new();
+  for line in text.lines() {
+    let line = line.split(['#', ';']).next().unwrap_or("");
+    for token in line.split_whitespace().filter(|t| !t.ends_with(':')) {
+      let digits = token.strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+        .unwrap_or(token);
+      if digits.is_empty() || digits.len() % 2 != 0
+        || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None
+      }
+      for i in (0..digits.len()).step_by(2) {
+        bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).ok()?);
+      }
+    }
+  }
+
+  if bytes.is_empty() { None } else { Some(bytes) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in files {
+      zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+      zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+  }
+
+  fn decode(data: Vec<u8>) -> io::Result<Rom> {
+    super::decode(Path::new("rom.zip"), data)
+  }
+
+  #[test]
+  fn reads_hex_dumps() {
+    let read = |name: &str, data: &str| {
+      super::decode(Path::new(name), data.as_bytes().to_vec())
+    };
+    let dump = "0200: 00e0 a22a ; clear\n# comment\n0x60 0X0C\n";
+    assert_eq!(read("rom.HEX", dump).unwrap().program,
+               vec![0x00, 0xe0, 0xa2, 0x2a, 0x60, 0x0c]);
+    assert!(read("rom.hex", "00e").is_err());
+    // Raw ROMs can be valid hex too
+    assert_eq!(read("rom.ch8", "00e0").unwrap().program, b"00e0".to_vec());
+  }
+
+  #[test]
+  fn unzips_single_roms() {
+    let rom = decode(zip(&[("pong/", b""), ("pong/pong.ch8", &[0x00, 0xe0]),
+                           ("pong/README", b"Pong"), (".DS_Store", b"")]))
+      .unwrap();
+    assert_eq!(rom.program, vec![0x00, 0xe0]);
+    assert!(rom.embedded.is_none());
+
+    assert_eq!(decode(zip(&[("pong", b"00e0")])).unwrap().program,
+               b"00e0".to_vec());
+    assert_eq!(decode(zip(&[("pong.hex", b"00e0")])).unwrap().program,
+               vec![0x00, 0xe0]);
+    assert!(decode(zip(&[("a.ch8", b""), ("b.ch8", b"")])).is_err());
+    assert!(decode(zip(&[("README", b""), ("LICENSE", b"")])).is_err());
+  }
+}
@@ -1,83 +1,405 @@
-mod chip8;
+mod capture;
+mod cart;
+mod config;
+mod disasmview;
+mod gamepad;
 mod glscreen;
+mod headless;
+mod keymap;
+mod keymapview;
+mod keypadview;
+mod loader;
 mod memview;
+mod menubar;
+mod palette;
+mod profview;
+mod rombrowser;
+mod romdb;
+mod scheduler;
+mod settings;
+mod shaderview;
+mod status;
+mod swscreen;
+mod swwindow;
+mod tui;
+
+// The emulator core, in its own crate so the web build can share it
+use chipers_core as chip8;
 
 use std::fs::File;
-use std::io::prelude::*;
+use std::io;
 
 use docopt::Docopt;
 use glium::{Surface, glutin::{self, VirtualKeyCode}};
-use imgui::{Context, im_str, Window};
+use imgui::{Context, ImString, im_str, MouseButton, Ui, Window};
 use imgui_glium_renderer::Renderer;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 use serde::Deserialize;
 use time::{Duration, SteadyTime};
 use std::time::Instant;
 
-use chip8::Chip8;
+use capture::Capture;
+use chip8::{Chip8, PERIOD_60HZ, ROM_START, Screen, Timing};
 use chip8::cpu::Cpu;
+use chip8::disasm::disassemble;
 use chip8::keyboard::SimpleKeyboard;
-use chip8::memory::WatchedRAM;
+use chip8::memory::{RAM_LENGTH, WatchedRAM};
+use chip8::profiler::Profiler;
+use disasmview::DisassemblyView;
+use gamepad::{GamepadMap, Gamepads};
 use glscreen::GLScreen;
+use keymap::Keymap;
+use keymapview::KeymapView;
+use keypadview::KeypadView;
 use memview::MemoryEditor;
+use menubar::{MenuAction, MenuBar};
+use palette::{Palette, PaletteConfig};
+use profview::ProfilerView;
+use rombrowser::RomBrowser;
+use romdb::{Platform, RomDb, RomInfo};
+use scheduler::{Clock, FrameScheduler};
+use settings::{DEFAULT_CPS, Settings, WindowState};
+use shaderview::ShaderView;
+use status::StatusLine;
 
 const TPF_HISTORY_LENGTH: usize = 128;
 const CPS_HISTORY_LENGTH: usize = 128;
 const TPF_REFRESH_PERIOD: f32 = 500.0; // ms
+const TURBO_STEP_MS: f32 = 1.0;
+const TICK_SLACK_US: i64 = 100;
 
 const USAGE: &'static str = "
 A Chip-8 emulator in Rust.
 
+ROMs are read from raw binaries, hex dumps (.hex files), zip archives holding a
+single ROM, and Octo cartridges (GIF images) whose program is a byte listing
+rather than Octo source.  The settings of cartridges are applied.
+`chipers cart` writes the ROM and its settings to an Octo cartridge instead of
+running it, labelled with the screen after --frames frames.
+
+Defaults for the zoom, fps, cps, shader, palette and layout are read from
+config.toml in the configuration directory ($XDG_CONFIG_HOME/chipers).
+
 Usage:
-  chipers [options] [-c <hz> | -t] <rom>
+  chipers [options] [-c <hz> | -i <n> | -t | --vip] <rom>
+  chipers cart [options] [-c <hz> | -i <n>] <rom> <cart>
   chipers -h
 
 Options:
   -h, --help              Show this help.
-  -z <int>, --zoom <int>  Set the zoom factor of the window [default: 10].
-  -f <hz>, --fps <hz>     Set the repaint frequency [default: 60].
-  -c <hz>, --cps <hz>     Set the CPU frequency [default: 600].
+  -z <int>, --zoom <int>  Set the zoom factor of the window [default: {zoom}].
+  -f <hz>, --fps <hz>     Set the repaint frequency [default: {fps}].
+  -c <hz>, --cps <hz>     Set the CPU frequency (default: {cps}).
+  -i <n>, --ipf <n>       Execute exactly <n> instructions per 60Hz frame,
+                          regardless of host timing.
+  --vip                   Emulate the instruction timings of the COSMAC VIP.
+  --display-wait          Wait for the 60Hz interrupt before drawing sprites.
+  --clip                  Clip sprites at the screen edges instead of wrapping.
+  --collision-rows        Set VF to the number of rows that collided or were
+                          clipped when drawing, as SCHIP does in hi-res mode.
   -t, --turbo             Emulate as fast as possible (for benchmarking).
-  -p, --plain             Do not use fancy CRT shader (much faster).
+  --vsync                 Synchronize repaints with the display.
+  --ff <factor>           Speed multiplier while Tab is held [default: 4].
+  --shader <name>         Shader used to draw the screen: plain, crt-phosphor,
+                          crt-lottes, phosphor-trail, the path to a GLSL
+                          fragment shader, or the path to a TOML preset of
+                          several passes.  Files are reloaded when they
+                          change.  F8 cycles through shaders
+                          [default: {shader}].
+  -p, --plain             Same as --shader plain (much faster).
+  -s, --software          Render without OpenGL.
+  --tui                   Run in the terminal.
+  --headless              Run without display or input, as fast as possible.
+  --frames <n>            Number of frames to run in headless mode, or before
+                          taking the label of a cartridge [default: 600].
+  --grid                  Show a pixel grid (software rendering only).
+  --persistence <f>       Fraction of brightness pixels keep on each frame
+                          after being turned off (software rendering only)
+                          [default: 0].
+  --palette <colors>      Color theme (plain, green, amber, lcd, octo, or one
+                          from palettes.toml), or background, foreground,
+                          second plane and both planes colors in hex, e.g.
+                          000000,ffffff,ff0000,ffff00.  The last two are
+                          optional.
+  --screenshot <file>     Save a PNG screenshot to <file> on exit.  F12 saves
+                          one in the current directory at any time.
+  --record <file>         Record the session to an animated GIF.  F11 starts
+                          and stops recording.
+  --record-cmd <cmd>      Record by piping raw RGBA frames to the shell
+                          command <cmd>, where {width}, {height} and {fps} are
+                          replaced, e.g. \"ffmpeg -f rawvideo -pix_fmt
+                          rgba -s {width}x{height} -r {fps} -i - out.mp4\".
+  --capture-scale <n>     Scale of screenshots and recordings [default: 10].
+  --layout <name>         Keyboard layout: colemak, qwerty, azerty, dvorak or
+                          numpad.  Defaults to the one of keymap.toml, or
+                          colemak.
+  --browser               Show the ROM browser.  F2 shows or hides it, and ROMs
+                          dropped on the window are loaded (OpenGL only).
+  --menu                  Show the menu and status bars over the game.  F10
+                          shows or hides them (OpenGL only).
+  --keypad                Show a keypad that can be clicked, and shows the
+                          keys pressed.  F9 shows or hides it (OpenGL only).
   -d, --debug             Show debug information.
+  --profile <file>        Write an execution profile to <file> on exit.
+  --coverage <file>       Write a coverage report of the ROM to <file> on exit.
 ";
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct Args {
+  cmd_cart: bool,
   arg_rom: String,
+  arg_cart: Option<String>,
   flag_zoom: usize,
   flag_fps: usize,
-  flag_cps: u64,
+  flag_cps: Option<u64>,
+  flag_ipf: Option<u32>,
+  flag_vip: bool,
+  flag_display_wait: bool,
+  flag_clip: bool,
+  flag_collision_rows: bool,
   flag_turbo: bool,
+  flag_vsync: bool,
+  flag_ff: f32,
+  flag_shader: String,
   flag_plain: bool,
+  flag_software: bool,
+  flag_tui: bool,
+  flag_headless: bool,
+  flag_frames: u64,
+  flag_grid: bool,
+  flag_persistence: f32,
+  flag_palette: Option<String>,
+  flag_screenshot: Option<String>,
+  flag_record: Option<String>,
+  flag_record_cmd: Option<String>,
+  flag_capture_scale: usize,
+  flag_layout: Option<String>,
+  flag_browser: bool,
+  flag_menu: bool,
+  flag_keypad: bool,
   flag_debug: bool,
+  flag_profile: Option<String>,
+  flag_coverage: Option<String>,
+  // Entry of the ROM database
+  #[serde(skip)]
+  rom_info: RomInfo,
+  // Contents of config.toml
+  #[serde(skip)]
+  settings: Settings,
+  // Size of the loaded ROM
+  #[serde(skip)]
+  rom_size: usize,
 }
 
 fn main() {
-  // Process args
-  let args: Args = Docopt::new(USAGE)
+  // Process args, with defaults from the configuration
+  let settings = Settings::load();
+  let mut cli: Args = Docopt::new(settings.usage(USAGE))
     .and_then(|d| d.deserialize())
     .unwrap_or_else(|e| e.exit());
+  cli.settings = settings;
+
+  // Init Chip8 and components
+  let mut chip8 = Chip8::new(Cpu::new(), WatchedRAM::new());
+  let mut args = load_rom(&cli, &cli.arg_rom, &mut chip8)
+    .expect("Error loading ROM");
+
+  if let (true, Some(path)) = (args.cmd_cart, &args.arg_cart) {
+    cart::export(&args, &mut chip8, path)
+      .expect("Error writing cartridge");
+  } else if args.flag_headless {
+    headless::run(&args, &mut chip8);
+  } else if args.flag_tui {
+    tui::run(&args, &mut chip8);
+  } else if args.flag_software {
+    swwindow::run(&args, &mut chip8);
+  } else {
+    run_gl(&cli, &mut args, &mut chip8);
+  }
+  status::flush();
+
+  if let (Some(path), Some(p)) = (&args.flag_profile, &chip8.cpu.profiler) {
+    let mut f = File::create(path)
+      .expect("Error creating profile report");
+    p.write_report(&mut f)
+      .expect("Error writing profile report");
+  }
+
+  if let Some(ref path) = args.flag_coverage {
+    let mut f = File::create(path)
+      .expect("Error creating coverage report");
+    chip8.ram.write_coverage_report(&mut f, 0x200..(0x200 + args.rom_size))
+      .expect("Error writing coverage report");
+  }
+}
+
+type Machine = Chip8<Cpu, WatchedRAM>;
+
+// Reset the machine with a new ROM.  Returns the options for this ROM: the
+// ones of the command line, completed by the ROM database.
+fn load_rom(cli: &Args, path: &str, chip8: &mut Machine) -> io::Result<Args> {
+  let rom = read_rom(path)?;
+  let buf = &rom.program;
+
+  let mut args = cli.clone();
+  args.arg_rom = path.to_string();
+  args.rom_size = buf.len();
+  if args.flag_debug {
+    eprintln!("ROM SHA-1: {}", romdb::sha1(buf));
+  }
+  apply_rom_info(&mut args, rom.info(&RomDb::load()).unwrap_or_default());
+  configure(&args, chip8);
+
+  if args.flag_debug || args.flag_profile.is_some() {
+    chip8.cpu.profiler = Some(Profiler::new());
+  }
 
-  // Time between each repaint
-  let target_repaint_ms = 1000.0 / args.flag_fps as f32;
-  let target_repaint_interval = Duration::microseconds(
-    (1000.0 * target_repaint_ms) as i64);
+  start(chip8, buf);
+  rombrowser::add_recent(path);
+  Ok(args)
+}
+
+// Set the timing and quirks of the machine from the options
+fn configure(args: &Args, chip8: &mut Machine) {
+  chip8.freq = args.flag_cps.or(args.settings.cps).unwrap_or(DEFAULT_CPS);
+  chip8.timing = match args.flag_ipf {
+    Some(ipf) => Timing::PerFrame(ipf),
+    None => Timing::RealTime,
+  };
+  chip8.cpu.quirks.display_wait = args.flag_display_wait;
+  chip8.cpu.quirks.clip_sprites = args.flag_clip;
+  chip8.cpu.quirks.collision_rows = args.flag_collision_rows;
+  if args.flag_vip {
+    chip8.timing = Timing::Vip;
+    chip8.cpu.quirks.display_wait = true;
+  }
+}
+
+fn start(chip8: &mut Machine, rom: &[u8]) {
+  chip8.reset();
+  chip8.load_rom(rom);
+  // Loading the ROM is not part of its coverage
+  chip8.ram.reset_coverage();
+}
+
+// Restart the current ROM, keeping the options changed while running
+fn reset_rom(args: &Args, chip8: &mut Machine) -> io::Result<()> {
+  start(chip8, &read_rom(&args.arg_rom)?.program);
+  Ok(())
+}
+
+// Read a ROM, which must fit in memory
+fn read_rom(path: &str) -> io::Result<loader::Rom> {
+  let rom = loader::load(path)?;
+  let max = RAM_LENGTH - ROM_START;
+  if rom.program.len() > max {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("ROM of {} bytes does not fit in memory ({} at most)",
+              rom.program.len(), max)))
+  }
+  Ok(rom)
+}
+
+// Use the settings of the ROM database for the options not given on the
+// command line.  Timings only apply if none was given.  Its palette, layout
+// and keys are kept in `rom_info`, under the per-ROM configuration files.
+fn apply_rom_info(args: &mut Args, info: RomInfo) {
+  if args.flag_cps.is_none() && args.flag_ipf.is_none() && !args.flag_vip
+    && !args.flag_turbo {
+    args.flag_cps = info.cps;
+    args.flag_ipf = info.ipf;
+    args.flag_vip = info.platform == Some(Platform::Vip);
+  }
+  args.flag_display_wait |= info.display_wait;
+  args.flag_clip |= info.clip;
+  args.flag_collision_rows |= info.collision_rows;
+  args.rom_info = info;
+}
+
+// The keymap from the configuration and the command line
+fn keymap(args: &Args) -> Keymap {
+  Keymap::load(&args.arg_rom, args.settings.layout.as_deref(),
+               &args.rom_info, args.flag_layout.as_deref())
+    .expect("Invalid keymap")
+}
 
+// Gamepads with the mapping of the configuration
+fn gamepads(args: &Args) -> Gamepads {
+  Gamepads::new(gamepad_map(args))
+}
+
+fn gamepad_map(args: &Args) -> GamepadMap {
+  GamepadMap::load(&args.rom_info.buttons)
+    .expect("Invalid gamepad mapping")
+}
+
+// The palette chosen on the command line or in the configuration, if any
+fn palette(args: &Args) -> Option<Palette> {
+  PaletteConfig::load()
+    .or_default(args.settings.palette.as_deref())
+    .select(args.flag_palette.as_deref(), &args.arg_rom,
+            args.rom_info.palette.as_deref())
+    .expect("Invalid palette")
+}
+
+// Speed of emulation: stopped while paused, faster while fast-forwarding
+fn speed(args: &Args, paused: bool, fast_forward: bool) -> f32 {
+  if paused { 0.0 }
+  else if fast_forward { args.flag_ff }
+  else { 1.0 }
+}
+
+// Start a new frame and emulate the machine for the time elapsed since the
+// previous one, times `speed`.  In turbo mode, emulate as much as possible
+// while leaving enough time to render the frame.  Returns the elapsed and
+// emulated times.
+fn emulate<S: Screen, C: Clock>(args: &Args, chip8: &mut Machine,
+                                scheduler: &mut FrameScheduler<C>, speed: f32,
+                                render_dt: Duration, screen: &mut S,
+                                keyboard: &mut SimpleKeyboard) -> (f32, f32) {
+  let real_dt = scheduler.start_frame();
+  let real_dt_ms = real_dt.num_microseconds().unwrap() as f32 / 1000.0;
+  let tick_slack = Duration::microseconds(TICK_SLACK_US);
+
+  let mut emulated_ms = 0.0;
+  if speed == 0.0 {
+    // Paused
+  } else if args.flag_turbo {
+    while scheduler.time_left() > render_dt + tick_slack {
+      chip8.run(TURBO_STEP_MS, screen, keyboard);
+      emulated_ms += TURBO_STEP_MS;
+    }
+  } else {
+    emulated_ms = real_dt_ms * speed;
+    chip8.run(emulated_ms, screen, keyboard);
+  }
+
+  (real_dt_ms, emulated_ms)
+}
+
+// Run the machine in an OpenGL window
+// `args` are replaced by the options of the ROMs loaded from the browser
+fn run_gl(cli: &Args, args: &mut Args, chip8: &mut Machine) {
   // Init Glium
   let zoom = args.flag_zoom;
+  let size = WindowState::load(zoom)
+    .unwrap_or(((glscreen::SCREEN_WIDTH * zoom) as f64,
+                (glscreen::SCREEN_HEIGHT * zoom) as f64));
 
   let mut events_loop = glium::glutin::EventsLoop::new();
   let wb = glium::glutin::WindowBuilder::new()
-    .with_title("Chipers")
-    .with_dimensions(((glscreen::SCREEN_WIDTH * zoom) as u32,
-                      (glscreen::SCREEN_HEIGHT * zoom) as u32).into());
-  let cb = glium::glutin::ContextBuilder::new();
+    .with_title(args.rom_info.window_title())
+    .with_dimensions(size.into());
+  let cb = glium::glutin::ContextBuilder::new()
+    .with_vsync(args.flag_vsync);
 //    .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (2, 1)));
   let display = glium::Display::new(wb, cb, &events_loop).unwrap();
 
-  // Init ImGui
+  // Init ImGui, keeping the positions of its windows in the configuration
   let mut imgui = Context::create();
+  imgui.set_ini_filename(config::file_path("imgui.ini")
+                         .map(|p| ImString::new(p.to_string_lossy())));
   let mut platform = WinitPlatform::init(&mut imgui);
   let gl_window = display.gl_window();
   let window = gl_window.window();
@@ -88,39 +410,32 @@ fn main() {
   let mut renderer = Renderer::init(&mut imgui, &display)
     .expect("Failed to initialize renderer");
 
-  // Init Chip8 and components
-  let mut screen = GLScreen::new(&display, args.flag_plain);
+  let shader = if args.flag_plain { "plain" } else { &args.flag_shader };
+  let mut screen = GLScreen::new(&display, shader);
   let mut keyboard = SimpleKeyboard::new();
-  let mut chip8 = Chip8::new(Cpu::new(), WatchedRAM::new());
-  chip8.freq = args.flag_cps;
+  let mut keymap = keymap(args);
+  let mut gamepads = gamepads(args);
 
-  let mut f = File::open(args.arg_rom)
-    .expect("Error opening ROM");
-  let mut buf = Vec::new();
-  f.read_to_end(&mut buf)
-    .expect("Error reading ROM");
-
-  chip8.reset();
-  chip8.load_rom(&buf);
-
-  // Debug stuff
-  let mut tpf_history = [0f32; TPF_HISTORY_LENGTH]; // time per frame
-  let mut tpf_history_idx = 0;
-  let mut avg_tpf = 0.0;
-  let mut cps_history = [0f32; CPS_HISTORY_LENGTH]; // chip8 per second
-  let mut cps_history_idx = 0;
-  let mut avg_cps = 0.0;
-
-  let mut tpf_refresh_counter = 0.0;
-  let mut overtimes = 0u64;
-  let mut memview = MemoryEditor::new();
+  let mut debug = DebugViews::new(args.flag_fps);
+  let mut keypadview = KeypadView::new(args.flag_keypad);
+  let mut rombrowser = RomBrowser::new(&args.arg_rom, args.flag_browser);
+  let mut menubar = MenuBar::new(args.flag_menu);
+  let mut status = StatusLine::new();
 
   // Main loop
-  let mut cpu_ticks_this_frame = 0f32;
-  let mut last_repaint = SteadyTime::now();
-  let tick_slack = Duration::microseconds(100);
-  let sleep_slack = Duration::microseconds(500);
+  let mut scheduler = FrameScheduler::new(args.flag_fps);
+  let mut render_dt = Duration::zero();
+  let mut fast_forward = false;
   let mut quit = false;
+  let palette = palette(args);
+  screen.palette = palette;
+  let mut capture = Capture::new(args, palette.unwrap_or_default());
+  let mut take_screenshot = false;
+  let mut toggle_recording = false;
+  let mut cycle_shader = false;
+  let mut rom_to_load = None;
+  let mut reset = false;
+  let mut advance = false;
 
   'running: loop {
     // Handle any key/mouse events
@@ -133,56 +448,59 @@ fn main() {
         match event {
           WindowEvent::CloseRequested => { quit = true },
 
+          WindowEvent::DroppedFile(path) => {
+            rom_to_load = Some(path.to_string_lossy().into_owned());
+          },
+
           WindowEvent::KeyboardInput { input, .. } => {
-            use glutin::ElementState::{Pressed, Released};
+            use glutin::ElementState::Pressed;
 
             match input {
+              // Keys pressed while editing bindings only go to the editor
+              KeyboardInput { state: Pressed, virtual_keycode: Some(vkey), .. }
+                if debug.keymapview.capture(&format!("{:?}", vkey), &mut keymap)
+              => {},
+
               KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::Escape), .. }
               => { quit = true },
 
-              KeyboardInput { state: Pressed, virtual_keycode: Some(vkey), .. } => {
-                match vkey {
-                  VirtualKeyCode::Key1 => keyboard.press_key(0x1),
-                  VirtualKeyCode::Key2 => keyboard.press_key(0x2),
-                  VirtualKeyCode::Key3 => keyboard.press_key(0x3),
-                  VirtualKeyCode::Q    => keyboard.press_key(0x4),
-                  VirtualKeyCode::W    => keyboard.press_key(0x5),
-                  VirtualKeyCode::F    => keyboard.press_key(0x6),
-                  VirtualKeyCode::A    => keyboard.press_key(0x7),
-                  VirtualKeyCode::R    => keyboard.press_key(0x8),
-                  VirtualKeyCode::S    => keyboard.press_key(0x9),
-                  VirtualKeyCode::Z    => keyboard.press_key(0x0),
-                  VirtualKeyCode::X    => keyboard.press_key(0xA),
-                  VirtualKeyCode::C    => keyboard.press_key(0xB),
-                  VirtualKeyCode::Key4 => keyboard.press_key(0xC),
-                  VirtualKeyCode::P    => keyboard.press_key(0xD),
-                  VirtualKeyCode::T    => keyboard.press_key(0xE),
-                  VirtualKeyCode::V    => keyboard.press_key(0xF),
-
-                  _ => ()
-                }
-              },
+              KeyboardInput { state, virtual_keycode: Some(VirtualKeyCode::Tab), .. }
+              => { fast_forward = state == Pressed },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F2), .. }
+              => { rombrowser.toggle() },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F3), .. }
+              => { reset = true },
 
-              KeyboardInput { state: Released, virtual_keycode: Some(vkey), .. } => {
-                match vkey {
-                  VirtualKeyCode::Key1 => keyboard.release_key(0x1),
-                  VirtualKeyCode::Key2 => keyboard.release_key(0x2),
-                  VirtualKeyCode::Key3 => keyboard.release_key(0x3),
-                  VirtualKeyCode::Q    => keyboard.release_key(0x4),
-                  VirtualKeyCode::W    => keyboard.release_key(0x5),
-                  VirtualKeyCode::F    => keyboard.release_key(0x6),
-                  VirtualKeyCode::A    => keyboard.release_key(0x7),
-                  VirtualKeyCode::R    => keyboard.release_key(0x8),
-                  VirtualKeyCode::S    => keyboard.release_key(0x9),
-                  VirtualKeyCode::Z    => keyboard.release_key(0x0),
-                  VirtualKeyCode::X    => keyboard.release_key(0xA),
-                  VirtualKeyCode::C    => keyboard.release_key(0xB),
-                  VirtualKeyCode::Key4 => keyboard.release_key(0xC),
-                  VirtualKeyCode::P    => keyboard.release_key(0xD),
-                  VirtualKeyCode::T    => keyboard.release_key(0xE),
-                  VirtualKeyCode::V    => keyboard.release_key(0xF),
-
-                  _ => ()
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F5), .. }
+              => { menubar.paused = !menubar.paused },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F6), .. }
+              => { advance = true },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F8), .. }
+              => { cycle_shader = true },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F9), .. }
+              => { keypadview.toggle() },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F10), .. }
+              => { menubar.toggle() },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F11), .. }
+              => { toggle_recording = true },
+
+              KeyboardInput { state: Pressed, virtual_keycode: Some(VirtualKeyCode::F12), .. }
+              => { take_screenshot = true },
+
+              KeyboardInput { state, virtual_keycode: Some(vkey), .. } => {
+                if let Some(c) = keymap.key(&format!("{:?}", vkey)) {
+                  if state == Pressed {
+                    keyboard.press_key(c);
+                  } else {
+                    keyboard.release_key(c);
+                  }
                 }
               },
 
@@ -198,24 +516,75 @@ fn main() {
     if quit {
       break 'running;
     }
+    gamepads.poll(&mut keyboard);
+
+    if let Some(path) = rom_to_load.take() {
+      match load_rom(cli, &path, chip8) {
+        Ok(a) => {
+          *args = a;
+          screen.clear();
+          keyboard = SimpleKeyboard::new();
+          keymap = crate::keymap(args);
+          gamepads.remap(gamepad_map(args));
+          screen.palette = crate::palette(args);
+          window.set_title(&args.rom_info.window_title());
+        },
+        Err(e) => status::post(
+          format!("Error loading {}: {}", path, e)),
+      }
+    }
 
-    // How much time has elapsed since last frame?
-    let now = SteadyTime::now();
-    let real_dt = now - last_repaint;
-    let real_dt_ms = real_dt.num_microseconds().unwrap() as f32 / 1000.0;
-    last_repaint = now;
+    if reset {
+      match reset_rom(args, chip8) {
+        Ok(()) => screen.clear(),
+        Err(e) => status::post(
+          format!("Error loading {}: {}", args.arg_rom, e)),
+      }
+      reset = false;
+    }
+
+    if cycle_shader {
+      screen.cycle_shader();
+      cycle_shader = false;
+    }
+    if toggle_recording {
+      capture.toggle_recording();
+      toggle_recording = false;
+    }
+    if take_screenshot {
+      capture.screenshot(screen.pixel_screen());
+      take_screenshot = false;
+    }
 
-    // Emulate the Chip8 for that period
     let before_emu = SteadyTime::now();
-    chip8.run(real_dt_ms, &mut screen, &mut keyboard);
+    let speed = speed(args, menubar.paused, fast_forward);
+    let (real_dt_ms, emulated_ms) = emulate(args, chip8, &mut scheduler,
+                                            speed, render_dt,
+                                            &mut screen, &mut keyboard);
+    // Frame advance pauses, then runs a single 60Hz frame
+    if advance {
+      menubar.paused = true;
+      chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+      advance = false;
+    }
+    menubar.measure(real_dt_ms, emulated_ms);
     let emu_dt = SteadyTime::now() - before_emu;
+    if args.flag_debug {
+      debug.measure(real_dt_ms, emulated_ms, emu_dt);
+    }
+    capture.frame(screen.pixel_screen());
 
     // Create frame and render
+    let before_render = SteadyTime::now();
     let mut frame = display.draw();
-    screen.repaint(&mut frame);
-
-    // Fill the debugging GUI if enabled
-    if args.flag_debug {
+    let area = game_area(frame.get_dimensions(), menubar.margins(),
+                         platform.hidpi_factor());
+    screen.repaint(&mut frame, area);
+
+    // Fill the GUI if there is anything to show
+    let message = status.current().map(str::to_string);
+    if args.flag_debug || menubar.open || keypadview.visible()
+      || rombrowser.open || message.is_some() {
       let io = imgui.io_mut();
       platform
         .prepare_frame(io, &window)
@@ -223,56 +592,28 @@ fn main() {
       io.update_delta_time(Instant::now());
       let ui = imgui.frame();
 
-      tpf_history[tpf_history_idx] = real_dt_ms;
-      tpf_history_idx = (tpf_history_idx + 1) % TPF_HISTORY_LENGTH;
-
-      ui.plot_histogram(
-        &im_str!("time per frame (ms)\navg: {:.3}ms\novertimes: {}",
-                 avg_tpf, overtimes), &tpf_history)
-        .values_offset(tpf_history_idx)
-        .graph_size([TPF_HISTORY_LENGTH as f32, 40.0])
-        .scale_min(0.0)
-        .scale_max(target_repaint_ms * 2.0)
-        .build();
-
-      // Going for nanoseconds otherwise we'll get zero for low CPU frequencies!
-      let emu_dt_ms = emu_dt.num_nanoseconds().unwrap() as f32 / 1_000_000.0;
-      cps_history[cps_history_idx] = real_dt_ms / emu_dt_ms;
-      cps_history_idx = (cps_history_idx + 1) % CPS_HISTORY_LENGTH;
-
-      ui.plot_histogram(
-        &im_str!("chip8 per second\navg: {:.1}cps", avg_cps), &cps_history)
-        .values_offset(cps_history_idx)
-        .graph_size([CPS_HISTORY_LENGTH as f32, 40.0])
-        .scale_min(0.0)
-        .scale_max(avg_cps * 2.0)
-        .build();
-
-      memview.draw(&ui, im_str!("Memory Editor"),
-                   &chip8.ram.read_all(), &chip8.ram.reads, &chip8.ram.writes);
-      chip8.ram.reset_reads_writes();
-
-      ui.window(im_str!("Registers"))
-        .build(|| {
-          ui.text(im_str!("pc: {:02x}", chip8.cpu.pc));
-          ui.text(im_str!("i: {:02x}", chip8.cpu.i));
-          ui.text(im_str!("delay: {:02x}", chip8.cpu.delay_timer));
-          ui.text(im_str!("sound: {:02x}", chip8.cpu.sound_timer));
-
-          for r in 0..chip8.cpu.v.len() {
-            ui.text(im_str!("v{}: {:02x}", r, chip8.cpu.v[r]));
-          }
-        });
-
-      // Update TPF and CPS averages every second
-      tpf_refresh_counter += real_dt_ms;
-      while tpf_refresh_counter > TPF_REFRESH_PERIOD {
-        avg_tpf = tpf_history.iter().fold(0f32, |a, &b| a + b)
-          / TPF_HISTORY_LENGTH as f32;
-        avg_cps = cps_history.iter().fold(0f32, |a, &b| a + b)
-          / CPS_HISTORY_LENGTH as f32;
+      match menubar.draw(&ui, chip8, capture.is_recording()) {
+        Some(MenuAction::OpenRom) => rombrowser.toggle(),
+        Some(MenuAction::Reset) => reset = true,
+        Some(MenuAction::Advance) => advance = true,
+        Some(MenuAction::CycleShader) => cycle_shader = true,
+        Some(MenuAction::ToggleKeypad) => keypadview.toggle(),
+        Some(MenuAction::Screenshot) => take_screenshot = true,
+        Some(MenuAction::ToggleRecording) => toggle_recording = true,
+        Some(MenuAction::Quit) => quit = true,
+        None => (),
+      }
+      if let Some(ref m) = message {
+        menubar.draw_message(&ui, m);
+      }
+      keypadview.draw(&ui, im_str!("Keypad"), &mut keyboard, &keymap);
+      if let Some(path) = rombrowser.draw(&ui, im_str!("ROMs")) {
+        rom_to_load = Some(path);
+      }
 
-        tpf_refresh_counter -= TPF_REFRESH_PERIOD;
+      if args.flag_debug {
+        debug.draw(&ui, chip8, &mut screen, &mut keymap, &args.arg_rom,
+                   scheduler.overtimes);
       }
 
       platform.prepare_render(&ui, &window);
@@ -282,43 +623,325 @@ fn main() {
 
     // Send to GPU
     frame.finish().unwrap();
+    render_dt = SteadyTime::now() - before_render;
+
+    // With vsync, finishing the frame already waited for the display
+    if !args.flag_vsync {
+      scheduler.wait();
+    }
+  }
+
+  capture.finish(screen.pixel_screen());
+
+  WindowState {
+    zoom,
+    size: window.get_inner_size().map(|s| s.into()),
+  }.save();
+}
+
+// Part of a frame of `size` pixels left to the game by bars taking `margins`
+// at the top and bottom, in logical pixels.  None if the game gets it all.
+fn game_area(size: (u32, u32), margins: (f32, f32),
+             scale: f64) -> Option<glium::Rect> {
+  let (width, height) = size;
+  let top = (margins.0 as f64 * scale).round() as u32;
+  let bottom = (margins.1 as f64 * scale).round() as u32;
+  if top + bottom == 0 || top + bottom >= height {
+    return None
+  }
+  Some(glium::Rect { left: 0, bottom, width, height: height - top - bottom })
+}
+
+// Debugging windows of the OpenGL front-end, and the timings they show
+struct DebugViews {
+  target_repaint_ms: f32,
+  tpf_history: [f32; TPF_HISTORY_LENGTH], // time per frame
+  tpf_history_idx: usize,
+  avg_tpf: f32,
+  cps_history: [f32; CPS_HISTORY_LENGTH], // chip8 per second
+  cps_history_idx: usize,
+  avg_cps: f32,
+  tpf_refresh_counter: f32,
+
+  memview: MemoryEditor,
+  profview: ProfilerView,
+  disasmview: DisassemblyView,
+  shaderview: ShaderView,
+  keymapview: KeymapView,
+}
+
+impl DebugViews {
+  fn new(fps: usize) -> Self {
+    Self {
+      // Time between each repaint
+      target_repaint_ms: 1000.0 / fps as f32,
+      tpf_history: [0.0; TPF_HISTORY_LENGTH],
+      tpf_history_idx: 0,
+      avg_tpf: 0.0,
+      cps_history: [0.0; CPS_HISTORY_LENGTH],
+      cps_history_idx: 0,
+      avg_cps: 0.0,
+      tpf_refresh_counter: 0.0,
+
+      memview: MemoryEditor::new(),
+      profview: ProfilerView::new(),
+      disasmview: DisassemblyView::new(),
+      shaderview: ShaderView::new(),
+      keymapview: KeymapView::new(),
+    }
+  }
+
+  fn measure(&mut self, real_dt_ms: f32, emulated_ms: f32, emu_dt: Duration) {
+    self.tpf_history[self.tpf_history_idx] = real_dt_ms;
+    self.tpf_history_idx = (self.tpf_history_idx + 1) % TPF_HISTORY_LENGTH;
+
+    // Going for nanoseconds otherwise we'll get zero for low CPU frequencies!
+    let emu_dt_ms = emu_dt.num_nanoseconds().unwrap() as f32 / 1_000_000.0;
+    self.cps_history[self.cps_history_idx] = emulated_ms / emu_dt_ms;
+    self.cps_history_idx = (self.cps_history_idx + 1) % CPS_HISTORY_LENGTH;
 
-    // // In turbo mode, use the remaining time to emulate as much as possible.
-    // if args.flag_turbo {
-    //   // Get close to the repaint interval, but leave room to avoid
-    //   // overshooting.
-    //   while since_last_repaint < (target_repaint_interval - tick_slack) {
-    //     chip8.run(&mut screen, &mut keyboard);
-    //     since_last_repaint = SteadyTime::now() - last_repaint;
-    //   }
-    // }
-
-
-
-    // // Still have time for this frame.  What do we do?
-    // if since_last_repaint < target_repaint_interval {
-    //   // Without turbo, just sleep
-    //   else {
-    //     // Sleep granularity depends on platform.  Subtract some slack to avoid
-    //     // oversleeping.
-    //     if target_repaint_interval - since_last_repaint > sleep_slack {
-    //       let wait = (target_repaint_interval - since_last_repaint - sleep_slack)
-    //         .to_std().unwrap();
-    //       std::thread::sleep(wait);
-    //     }
-    //   }
-
-    //   // If there is still time left, busy wait
-    //   while since_last_repaint < target_repaint_interval {
-    //     since_last_repaint = SteadyTime::now() - last_repaint;
-    //   }
-    // }
-    // // Above target interval: we took too much time and are late to repaint.
-    // else {
-    //   overtimes += 1;
-    // }
-
-    // last_repaint = SteadyTime::now();
+    // Update TPF and CPS averages every second
+    self.tpf_refresh_counter += real_dt_ms;
+    while self.tpf_refresh_counter > TPF_REFRESH_PERIOD {
+      self.avg_tpf = self.tpf_history.iter().fold(0f32, |a, &b| a + b)
+        / TPF_HISTORY_LENGTH as f32;
+      self.avg_cps = self.cps_history.iter().fold(0f32, |a, &b| a + b)
+        / CPS_HISTORY_LENGTH as f32;
+
+      self.tpf_refresh_counter -= TPF_REFRESH_PERIOD;
+    }
+  }
+
+  fn draw(&mut self, ui: &Ui, chip8: &mut Machine, screen: &mut GLScreen,
+          keymap: &mut Keymap, rom: &str, overtimes: u64) {
+    ui.plot_histogram(
+      &im_str!("time per frame (ms)\navg: {:.3}ms\novertimes: {}",
+               self.avg_tpf, overtimes), &self.tpf_history)
+      .values_offset(self.tpf_history_idx)
+      .graph_size([TPF_HISTORY_LENGTH as f32, 40.0])
+      .scale_min(0.0)
+      .scale_max(self.target_repaint_ms * 2.0)
+      .build();
+
+    ui.plot_histogram(
+      &im_str!("chip8 per second\navg: {:.1}cps", self.avg_cps),
+      &self.cps_history)
+      .values_offset(self.cps_history_idx)
+      .graph_size([CPS_HISTORY_LENGTH as f32, 40.0])
+      .scale_min(0.0)
+      .scale_max(self.avg_cps * 2.0)
+      .build();
+
+    self.memview.draw(ui, im_str!("Memory Editor"),
+                      &chip8.ram.read_all(), &chip8.ram.reads,
+                      &chip8.ram.writes);
+    chip8.ram.reset_reads_writes();
+
+    if let Some(ref p) = chip8.cpu.profiler {
+      self.profview.draw(ui, im_str!("Profiler"), p);
+    }
+
+    ui.window(im_str!("Registers"))
+      .build(|| {
+        ui.text(im_str!("pc: {:02x}", chip8.cpu.pc));
+        ui.text(im_str!("i: {:02x}", chip8.cpu.i));
+        ui.text(im_str!("delay: {:02x}", chip8.cpu.delay_timer));
+        ui.text(im_str!("sound: {:02x}", chip8.cpu.sound_timer));
+
+        for r in 0..chip8.cpu.v.len() {
+          ui.text(im_str!("v{}: {:02x}", r, chip8.cpu.v[r]));
+        }
+      });
+
+    self.disasmview.draw(ui, im_str!("Disassembly"),
+                         chip8.ram.read_all(), chip8.cpu.pc);
+
+    self.shaderview.draw(ui, im_str!("Shader"), screen.chain_mut());
+
+    self.keymapview.draw(ui, im_str!("Key Bindings"), keymap, rom);
+
+    // Show which instruction last toggled the pixel under the mouse, and
+    // jump to it on click
+    let [w, h] = ui.io().display_size;
+    let [sx, sy] = ui.io().display_framebuffer_scale;
+    // The mouse is in points from the top left, and the game area in pixels
+    // from the bottom left
+    let (left, top, aw, ah) = match screen.area() {
+      Some(r) => (r.left as f32 / sx,
+                  h - (r.bottom + r.height) as f32 / sy,
+                  r.width as f32 / sx,
+                  r.height as f32 / sy),
+      None => (0.0, 0.0, w, h),
+    };
+    let [mx, my] = ui.io().mouse_pos;
+    let (mx, my) = (mx - left, my - top);
+    if !ui.io().want_capture_mouse
+      && mx >= 0.0 && my >= 0.0 && mx < aw && my < ah {
+      let x = (mx / aw * glscreen::SCREEN_WIDTH as f32) as usize;
+      let y = (my / ah * glscreen::SCREEN_HEIGHT as f32) as usize;
+
+      if let Some(o) = screen.pixel_screen().origin(x, y) {
+        let mem = chip8.ram.read_all();
+        // The draw may be at the very end of memory
+        let byte = |a: usize| mem.get(a).copied().unwrap_or(0) as u16;
+        let opcode = (byte(o.pc) << 8) | byte(o.pc + 1);
+
+        ui.tooltip(|| {
+          ui.text(im_str!("pixel ({}, {})", x, y));
+          ui.text(im_str!("drawn by {:03x}: {}", o.pc, disassemble(opcode)));
+          ui.text(im_str!("i: {:03x}", o.i));
+          ui.text(im_str!("frame: {}", o.frame));
+        });
+
+        if ui.is_mouse_clicked(MouseButton::Left) {
+          self.disasmview.goto(o.pc);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chip8::screen::PixelScreen;
+  use scheduler::tests::FakeClock;
+
+  fn parse(argv: &[&str], settings: Settings) -> Args {
+    let mut args: Args = Docopt::new(settings.usage(USAGE))
+      .and_then(|d| d.argv(argv.iter()).deserialize())
+      .unwrap();
+    args.settings = settings;
+    args
+  }
+
+  // Machine configured from the command line, the ROM database and config.toml
+  fn configured(argv: &[&str], info: RomInfo, settings: Settings) -> Machine {
+    let mut args = parse(argv, settings);
+    apply_rom_info(&mut args, info);
+    let mut chip8 = Chip8::new(Cpu::new(), WatchedRAM::new());
+    configure(&args, &mut chip8);
+    chip8
+  }
+
+  #[test]
+  fn rejects_roms_larger_than_memory() {
+    let path = std::env::temp_dir()
+      .join(format!("chipers-{}-large.ch8", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    std::fs::write(path, vec![0x12; RAM_LENGTH - ROM_START]).unwrap();
+    assert!(read_rom(path).is_ok());
+    std::fs::write(path, vec![0x12; RAM_LENGTH - ROM_START + 1]).unwrap();
+    let e = read_rom(path).err().unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+  }
+
+  // Emulate a frame started `frame_ms` after the previous one, at the given
+  // speed
+  fn emulate_frame(args: &Args, clock: &FakeClock,
+                   scheduler: &mut FrameScheduler<FakeClock>, speed: f32,
+                   frame_ms: i64) -> (f32, f32) {
+    let mut chip8 = Chip8::new(Cpu::new(), WatchedRAM::new());
+    chip8.load_rom(&[0x12, 0x00]); // JP 200
+    clock.advance(Duration::milliseconds(frame_ms));
+    emulate(args, &mut chip8, scheduler, speed,
+            Duration::milliseconds(5), &mut PixelScreen::new(),
+            &mut SimpleKeyboard::new())
+  }
+
+  #[test]
+  fn emulates_the_time_that_passed() {
+    let args = parse(&["chipers", "a.ch8"], Settings::default());
+    let clock = FakeClock::new();
+    let mut scheduler = FrameScheduler::with_clock(50, clock.clone());
+
+    let (real_ms, emulated_ms) = emulate_frame(&args, &clock, &mut scheduler,
+                                               1.0, 20);
+    assert!((real_ms - 20.0).abs() < 0.1);
+    assert_eq!(emulated_ms, real_ms);
+
+    // A frame that takes too long to render is caught up on the next one
+    clock.advance(Duration::milliseconds(45));
+    scheduler.wait();
+    assert_eq!(scheduler.overtimes, 1);
+    let (real_ms, emulated_ms) = emulate_frame(&args, &clock, &mut scheduler,
+                                               1.0, 0);
+    assert!((real_ms - 45.0).abs() < 0.1);
+    assert_eq!(emulated_ms, real_ms);
+
+    let (_, emulated_ms) = emulate_frame(&args, &clock, &mut scheduler,
+                                         0.0, 20);
+    assert_eq!(emulated_ms, 0.0);
+  }
+
+  #[test]
+  fn fast_forward_multiplies_the_speed() {
+    let args = parse(&["chipers", "--ff", "3", "a.ch8"], Settings::default());
+    let clock = FakeClock::new();
+    let mut scheduler = FrameScheduler::with_clock(50, clock.clone());
+
+    let (real_ms, emulated_ms) = emulate_frame(&args, &clock, &mut scheduler,
+                                               speed(&args, false, true), 20);
+    assert_eq!(emulated_ms, real_ms * 3.0);
+    assert_eq!(speed(&args, true, true), 0.0);
+  }
+
+  #[test]
+  fn turbo_emulates_until_it_is_time_to_render() {
+    let args = parse(&["chipers", "--turbo", "a.ch8"], Settings::default());
+    let clock = FakeClock::new();
+    let mut scheduler = FrameScheduler::with_clock(50, clock.clone());
+
+    // Steps take no time on the fake clock but its readings, so many more
+    // are run than in real time.  The 5ms needed to render are left, whatever
+    // the time spent on the previous frame.
+    for &frame_ms in &[0, 40] {
+      let (real_ms, emulated_ms) = emulate_frame(&args, &clock,
+                                                 &mut scheduler, 1.0,
+                                                 frame_ms);
+      assert!(emulated_ms > real_ms + 1000.0);
+      let left = scheduler.time_left();
+      assert!(left > Duration::milliseconds(5));
+      assert!(left <= Duration::microseconds(5_000 + TICK_SLACK_US + 10));
+    }
+  }
+
+  #[test]
+  fn leaves_room_for_the_bars() {
+    assert!(game_area((640, 320), (0.0, 0.0), 1.0).is_none());
+    let r = game_area((640, 320), (20.0, 30.0), 2.0).unwrap();
+    assert_eq!((r.left, r.bottom, r.width, r.height), (0, 60, 640, 220));
+    assert!(game_area((640, 40), (20.0, 30.0), 1.0).is_none());
+  }
 
+  #[test]
+  fn options_win_over_rom_database_and_config() {
+    let config = Settings { cps: Some(1000), ..Settings::default() };
+    let db = RomInfo { cps: Some(700), clip: true, ..RomInfo::default() };
+
+    let chip8 = configured(&["chipers", "a.ch8"], RomInfo::default(),
+                           Settings::default());
+    assert_eq!(chip8.freq, DEFAULT_CPS);
+    let chip8 = configured(&["chipers", "a.ch8"], RomInfo::default(),
+                           config.clone());
+    assert_eq!(chip8.freq, 1000);
+    let chip8 = configured(&["chipers", "a.ch8"], db.clone(), config.clone());
+    assert_eq!(chip8.freq, 700);
+    assert!(chip8.cpu.quirks.clip_sprites);
+    let chip8 = configured(&["chipers", "-c", "900", "a.ch8"], db.clone(),
+                           config.clone());
+    assert_eq!(chip8.freq, 900);
+
+    // Any timing option replaces the timing of the database
+    let chip8 = configured(&["chipers", "--ipf", "5", "a.ch8"], db, config);
+    assert!(chip8.timing == Timing::PerFrame(5));
+    assert_eq!(chip8.freq, 1000);
+
+    let db = RomInfo { platform: Some(Platform::Vip), ..RomInfo::default() };
+    let chip8 = configured(&["chipers", "a.ch8"], db, Settings::default());
+    assert!(chip8.timing == Timing::Vip);
+    assert!(chip8.cpu.quirks.display_wait);
   }
 }
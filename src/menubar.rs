@@ -0,0 +1,167 @@
+use imgui::{Condition, Ui, im_str};
+
+use crate::Machine;
+use crate::chip8::Timing;
+
+const STATUS_HEIGHT: f32 = 30.0;
+const MESSAGE_MARGIN: f32 = 10.0;
+// Weight of the last frame in the displayed speed
+const SPEED_SMOOTHING: f32 = 0.05;
+const MAX_FREQ: i32 = 5000;
+const MAX_IPF: i32 = 1000;
+
+// Menu entries handled by the main loop
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuAction {
+  OpenRom,
+  Reset,
+  Advance,
+  CycleShader,
+  ToggleKeypad,
+  Screenshot,
+  ToggleRecording,
+  Quit,
+}
+
+pub struct MenuBar {
+  pub open: bool,
+  pub paused: bool,
+  // Height of the menu bar, known once drawn
+  menu_height: f32,
+  // Emulated time over elapsed time, smoothed
+  speed: f32,
+}
+
+impl MenuBar {
+  pub fn new(open: bool) -> MenuBar {
+    MenuBar {
+      open,
+      paused: false,
+      menu_height: 0.0,
+      speed: 1.0,
+    }
+  }
+
+  pub fn toggle(&mut self) {
+    self.open = !self.open;
+  }
+
+  // Space taken by the bars at the top and bottom of the window, which the
+  // game is drawn between
+  pub fn margins(&self) -> (f32, f32) {
+    if self.open { (self.menu_height, STATUS_HEIGHT) } else { (0.0, 0.0) }
+  }
+
+  pub fn measure(&mut self, real_ms: f32, emulated_ms: f32) {
+    if real_ms > 0.0 {
+      self.speed += (emulated_ms / real_ms - self.speed) * SPEED_SMOOTHING;
+    }
+  }
+
+  // Draw the menu bar at the top of the window and the status bar at the
+  // bottom.  Returns the menu entry picked, if any.
+  pub fn draw(&mut self, ui: &Ui, chip8: &mut Machine,
+              recording: bool) -> Option<MenuAction> {
+    if !self.open {
+      return None
+    }
+
+    let mut action = None;
+    let paused = &mut self.paused;
+    let menu_height = &mut self.menu_height;
+    ui.main_menu_bar(|| {
+      *menu_height = ui.get_window_size()[1];
+
+      let mut item = |label, shortcut, a| {
+        if ui.menu_item(label).shortcut(shortcut).build() {
+          action = Some(a);
+        }
+      };
+
+      ui.menu(im_str!("File")).build(|| {
+        item(im_str!("Open ROM..."), im_str!("F2"), MenuAction::OpenRom);
+        item(im_str!("Quit"), im_str!("Escape"), MenuAction::Quit);
+      });
+
+      ui.menu(im_str!("Emulation")).build(|| {
+        ui.menu_item(im_str!("Pause")).shortcut(im_str!("F5"))
+          .selected(paused).build();
+        item(im_str!("Frame advance"), im_str!("F6"), MenuAction::Advance);
+        item(im_str!("Reset"), im_str!("F3"), MenuAction::Reset);
+      });
+
+      ui.menu(im_str!("View")).build(|| {
+        item(im_str!("Keypad"), im_str!("F9"), MenuAction::ToggleKeypad);
+        item(im_str!("Next shader"), im_str!("F8"), MenuAction::CycleShader);
+        item(im_str!("Screenshot"), im_str!("F12"), MenuAction::Screenshot);
+        item(if recording { im_str!("Stop recording") }
+             else { im_str!("Record") },
+             im_str!("F11"), MenuAction::ToggleRecording);
+      });
+    });
+
+    let [w, h] = ui.io().display_size;
+    let speed = self.speed;
+    ui.window(im_str!("Status"))
+      .position([0.0, h - STATUS_HEIGHT], Condition::Always)
+      .size([w, STATUS_HEIGHT], Condition::Always)
+      .title_bar(false)
+      .resizable(false)
+      .movable(false)
+      .scroll_bar(false)
+      .collapsible(false)
+      .save_settings(false)
+      .build(|| {
+        ui.text(if *paused { im_str!("paused") } else { im_str!("running") });
+        ui.same_line(0.0);
+        ui.text(im_str!("speed: {:3.0}%", speed * 100.0));
+        ui.same_line(0.0);
+
+        // Effective frequency, and a slider for the requested one
+        let _width = ui.push_item_width(200.0);
+        match chip8.timing {
+          Timing::RealTime => {
+            ui.text(im_str!("({:.0} Hz)", chip8.freq as f32 * speed));
+            ui.same_line(0.0);
+            let mut freq = chip8.freq.min(MAX_FREQ as u64) as i32;
+            if ui.slider_int(im_str!("Hz"), &mut freq, 1, MAX_FREQ).build() {
+              chip8.freq = freq as u64;
+            }
+          },
+          Timing::PerFrame(ref mut ipf) => {
+            ui.text(im_str!("({:.0} Hz)", *ipf as f32 * 60.0 * speed));
+            ui.same_line(0.0);
+            let mut i = (*ipf).min(MAX_IPF as u32) as i32;
+            if ui.slider_int(im_str!("instructions per frame"), &mut i,
+                             1, MAX_IPF).build() {
+              *ipf = i as u32;
+            }
+          },
+          Timing::Vip => ui.text(im_str!("COSMAC VIP timing")),
+        }
+
+        if recording {
+          ui.same_line(0.0);
+          ui.text(im_str!("recording"));
+        }
+      });
+
+    action
+  }
+
+  // Show a message in the bottom left corner, above the status bar if open
+  pub fn draw_message(&self, ui: &Ui, message: &str) {
+    let [_, h] = ui.io().display_size;
+    let bottom = if self.open { h - STATUS_HEIGHT } else { h };
+    ui.window(im_str!("Message"))
+      .position([MESSAGE_MARGIN, bottom - MESSAGE_MARGIN], Condition::Always)
+      .position_pivot([0.0, 1.0])
+      .title_bar(false)
+      .resizable(false)
+      .movable(false)
+      .always_auto_resize(true)
+      .save_settings(false)
+      .inputs(false)
+      .build(|| ui.text(message));
+  }
+}
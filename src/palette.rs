@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::config;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Colors used to render the logical screen
+
+// Background, then the colors of pixels in the first plane, in the second
+// plane, and in both planes (XO-CHIP)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palette {
+  pub colors: [[u8; 3]; 4],
+}
+
+impl Default for Palette {
+  // Same colors as the plain shader
+  fn default() -> Self {
+    Self {
+      colors: [[0x00, 0x00, 0xff], [0xff, 0x00, 0xff],
+               [0x80, 0x00, 0xff], [0xff, 0x80, 0xff]],
+    }
+  }
+}
+
+pub const THEMES: [(&str, Palette); 5] = [
+  ("plain", Palette {
+    colors: [[0x00, 0x00, 0xff], [0xff, 0x00, 0xff],
+             [0x80, 0x00, 0xff], [0xff, 0x80, 0xff]],
+  }),
+  ("green", Palette {
+    colors: [[0x05, 0x14, 0x05], [0x33, 0xff, 0x33],
+             [0x14, 0x8c, 0x14], [0xb0, 0xff, 0xb0]],
+  }),
+  ("amber", Palette {
+    colors: [[0x1a, 0x0e, 0x00], [0xff, 0xb0, 0x00],
+             [0xa8, 0x60, 0x00], [0xff, 0xdc, 0x80]],
+  }),
+  ("lcd", Palette {
+    colors: [[0x9b, 0xbc, 0x0f], [0x0f, 0x38, 0x0f],
+             [0x30, 0x62, 0x30], [0x8b, 0xac, 0x0f]],
+  }),
+  ("octo", Palette {
+    colors: [[0x99, 0x66, 0x00], [0xff, 0xcc, 0x00],
+             [0xff, 0x66, 0x00], [0x66, 0x22, 0x00]],
+  }),
+];
+
+impl Palette {
+  pub fn background(&self) -> [u8; 3] {
+    self.colors[0]
+  }
+
+  pub fn color(&self, pixel: u8) -> [u8; 3] {
+    self.colors[(pixel & 3) as usize]
+  }
+
+  pub fn theme(name: &str) -> Option<Self> {
+    THEMES.iter().find(|t| t.0 == name).map(|t| t.1)
+  }
+}
+
+// Parse a theme name, or two to four hex colors, e.g. "000000,ffffff".  With
+// two colors, both planes use the foreground color.
+impl FromStr for Palette {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if let Some(p) = Palette::theme(s.trim()) {
+      return Ok(p)
+    }
+
+    let colors = s.split(',')
+      .map(parse_color)
+      .collect::<Result<Vec<_>, _>>()?;
+
+    match colors[..] {
+      [bg, fg] => Ok(Self { colors: [bg, fg, fg, fg] }),
+      [bg, fg, fg2] => Ok(Self { colors: [bg, fg, fg2, fg] }),
+      [bg, fg, fg2, blend] => Ok(Self { colors: [bg, fg, fg2, blend] }),
+      _ => Err(format!("expected a theme or two to four colors, got '{}'", s)),
+    }
+  }
+}
+
+// Parse a "rrggbb" color, with an optional leading '#'
+pub fn parse_color(s: &str) -> Result<[u8; 3], String> {
+  let hex = s.trim().trim_start_matches('#');
+  let rgb = u32::from_str_radix(hex, 16)
+    .ok()
+    .filter(|_| hex.len() == 6)
+    .ok_or_else(|| format!("invalid color '{}'", s))?;
+
+  Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+// Contents of palettes.toml in the configuration directory:
+//
+//   default = "amber"
+//
+//   [themes]
+//   paper = "eeeeee,222222"
+//
+//   [roms]
+//   "pong.ch8" = "paper"
+//
+// Themes use the syntax of the --palette option, and ROMs are matched by file
+// name.
+#[derive(Deserialize, Default)]
+pub struct PaletteConfig {
+  default: Option<String>,
+  #[serde(default)]
+  themes: HashMap<String, String>,
+  #[serde(default)]
+  roms: HashMap<String, String>,
+}
+
+impl PaletteConfig {
+  pub fn load() -> Self {
+    config::load("palettes.toml")
+  }
+
+  // User themes take precedence over builtin ones
+  pub fn parse(&self, name: &str) -> Result<Palette, String> {
+    match self.themes.get(name) {
+      Some(colors) => colors.parse(),
+      None => name.parse(),
+    }
+  }
+
+  // Default palette when the file has none
+  pub fn or_default(mut self, name: Option<&str>) -> Self {
+    self.default = self.default.or_else(|| name.map(String::from));
+    self
+  }
+
+  // Pick the palette from the command line, then the one of the file for the
+  // ROM, then the one of the ROM database, then the default one, if any
+  pub fn select(&self, option: Option<&str>, rom: &str,
+                rom_palette: Option<&str>) -> Result<Option<Palette>, String> {
+    let rom_name = Path::new(rom).file_name()
+      .and_then(|n| n.to_str())
+      .unwrap_or(rom);
+
+    option
+      .or_else(|| self.roms.get(rom_name).map(String::as_str))
+      .or(rom_palette)
+      .or(self.default.as_deref())
+      .map(|name| self.parse(name))
+      .transpose()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_hex_colors() {
+    let p: Palette = "#102030, ffffff".parse().unwrap();
+    assert_eq!(p.background(), [0x10, 0x20, 0x30]);
+    assert_eq!(p.color(1), [0xff, 0xff, 0xff]);
+    assert_eq!(p.color(3), [0xff, 0xff, 0xff]);
+
+    let p: Palette = "000000,111111,222222,333333".parse().unwrap();
+    assert_eq!(p.color(2), [0x22, 0x22, 0x22]);
+    assert_eq!(p.color(3), [0x33, 0x33, 0x33]);
+
+    assert!("ffffff".parse::<Palette>().is_err());
+    assert!("fff,000".parse::<Palette>().is_err());
+    assert!("gggggg,000000".parse::<Palette>().is_err());
+  }
+
+  #[test]
+  fn selects_palettes_by_precedence() {
+    let config: PaletteConfig = toml::from_str(r#"
+      default = "amber"
+
+      [themes]
+      paper = "eeeeee,222222"
+
+      [roms]
+      "pong.ch8" = "paper"
+    "#).unwrap();
+
+    let amber = Palette::theme("amber");
+    let paper = Some("eeeeee,222222".parse().unwrap());
+    let lcd = Palette::theme("lcd");
+    assert_eq!(config.select(None, "roms/tetris.ch8", None), Ok(amber));
+    assert_eq!(config.select(None, "roms/pong.ch8", None), Ok(paper));
+    assert_eq!(config.select(Some("lcd"), "roms/pong.ch8", None), Ok(lcd));
+    assert!(config.select(Some("mauve"), "pong.ch8", None).is_err());
+    assert_eq!(PaletteConfig::default().select(None, "pong.ch8", None),
+               Ok(None));
+
+    // The ROM database comes after the file for the ROM
+    assert_eq!(config.select(None, "tetris.ch8", Some("lcd")), Ok(lcd));
+    assert_eq!(config.select(None, "pong.ch8", Some("lcd")), Ok(paper));
+  }
+
+  #[test]
+  fn parses_themes() {
+    assert_eq!("amber".parse::<Palette>(), Ok(Palette::theme("amber").unwrap()));
+    assert_eq!("plain".parse::<Palette>(), Ok(Palette::default()));
+    assert!("mauve".parse::<Palette>().is_err());
+  }
+}
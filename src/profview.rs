@@ -0,0 +1,53 @@
+use imgui::{Ui, ImStr, im_str};
+
+use crate::chip8::profiler::Profiler;
+
+const HOT_SPOTS_SHOWN: usize = 16;
+
+pub struct ProfilerView {
+  open: bool,
+}
+
+impl ProfilerView {
+  pub fn new() -> ProfilerView {
+    ProfilerView {
+      open: true,
+    }
+  }
+
+  pub fn draw(&mut self, ui: &Ui, title: &ImStr, profiler: &Profiler) {
+    let draws: Vec<f32> = profiler.draw_history.iter()
+      .map(|&d| d as f32)
+      .collect();
+
+    ui.window(title)
+      .opened(&mut self.open)
+      .build(|| {
+        ui.text(im_str!("instructions: {}", profiler.total_instructions));
+        ui.text(im_str!("waiting for key: {} cycles, {:.2}s",
+                        profiler.key_wait_cycles,
+                        profiler.key_wait_frames as f32 / 60.0));
+
+        ui.plot_histogram(
+          &im_str!("draws per frame\nmax: {}", profiler.max_draws_per_frame),
+          &draws)
+          .graph_size([draws.len() as f32, 40.0])
+          .scale_min(0.0)
+          .build();
+
+        ui.separator();
+        ui.text(im_str!("hot spots"));
+        for (addr, n) in profiler.hot_spots().into_iter().take(HOT_SPOTS_SHOWN) {
+          ui.text(im_str!("{:03x}: {}", addr, n));
+        }
+
+        ui.separator();
+        ui.text(im_str!("subroutines (calls/self/total)"));
+        for (addr, s) in profiler.hot_subroutines().into_iter()
+          .take(HOT_SPOTS_SHOWN) {
+          ui.text(im_str!("{:03x}: {}/{}/{}", addr, s.calls,
+                          s.self_instructions, s.total_instructions));
+        }
+      });
+  }
+}
@@ -0,0 +1,246 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use imgui::{ImGuiSelectableFlags, ImStr, Ui, im_str};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::loader::{self, is_rom_name};
+use crate::romdb::RomDb;
+
+const RECENT_FILE: &str = "recent.toml";
+const MAX_RECENT: usize = 10;
+// Larger files are not looked up in the ROM database
+const MAX_ROM_SIZE: u64 = 0x10000;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Recently loaded ROMs, most recent first, in recent.toml
+
+#[derive(Deserialize, Serialize, Default)]
+struct Recent {
+  files: Vec<String>,
+}
+
+pub fn recent_files() -> Vec<String> {
+  config::load::<Recent>(RECENT_FILE).files
+}
+
+pub fn add_recent(path: &str) {
+  let path = fs::canonicalize(path)
+    .map(|p| p.to_string_lossy().into_owned())
+    .unwrap_or_else(|_| path.to_string());
+  let mut files = recent_files();
+  push_recent(&mut files, path);
+
+  if let Err(e) = config::save(RECENT_FILE, &Recent { files }) {
+    eprintln!("Error saving recent files: {}", e);
+  }
+}
+
+fn push_recent(files: &mut Vec<String>, path: String) {
+  files.retain(|f| *f != path);
+  files.insert(0, path);
+  files.truncate(MAX_RECENT);
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Directory listing to pick a ROM from
+
+struct Entry {
+  path: PathBuf,
+  name: String,
+  is_dir: bool,
+  // Title and description from the ROM database
+  title: Option<String>,
+  description: Option<String>,
+}
+
+// Directories first, then files, by name
+fn list_dir(dir: &Path, all_files: bool, db: &RomDb) -> Vec<Entry> {
+  let mut entries: Vec<Entry> = match fs::read_dir(dir) {
+    Ok(d) => d.filter_map(|e| e.ok()).collect::<Vec<_>>(),
+    Err(e) => {
+      eprintln!("Error reading {}: {}", dir.display(), e);
+      Vec::new()
+    },
+  }.into_iter()
+    .filter_map(|e| {
+      let path = e.path();
+      let name = e.file_name().to_string_lossy().into_owned();
+      let is_dir = path.is_dir();
+      if name.starts_with('.') {
+        return None
+      }
+      if !is_dir && !all_files && !is_rom_name(&path) {
+        return None
+      }
+
+      let info = if is_dir { None } else {
+        fs::metadata(&path).ok()
+          .filter(|m| m.len() <= MAX_ROM_SIZE)
+          .and_then(|_| loader::load(&path).ok())
+          .and_then(|rom| rom.info(db))
+      };
+      Some(Entry {
+        path,
+        name,
+        is_dir,
+        title: info.as_ref().and_then(|i| i.title.clone()),
+        description: info.and_then(|i| i.description),
+      })
+    })
+    .collect();
+
+  entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+  entries
+}
+
+pub struct RomBrowser {
+  pub open: bool,
+  dir: PathBuf,
+  all_files: bool,
+  // Listing of `dir`, read again when the directory changes
+  entries: Option<Vec<Entry>>,
+  recent: Option<Vec<String>>,
+}
+
+impl RomBrowser {
+  // Start in the directory of the current ROM
+  pub fn new(rom: &str, open: bool) -> RomBrowser {
+    let dir = fs::canonicalize(rom).ok()
+      .and_then(|p| p.parent().map(Path::to_path_buf))
+      .unwrap_or_else(|| PathBuf::from("."));
+
+    RomBrowser {
+      open,
+      dir,
+      all_files: false,
+      entries: None,
+      recent: None,
+    }
+  }
+
+  pub fn toggle(&mut self) {
+    self.open = !self.open;
+    // See new files when opening again
+    self.entries = None;
+    self.recent = None;
+  }
+
+  // Returns the ROM to load, if one was picked
+  pub fn draw(&mut self, ui: &Ui, title: &ImStr) -> Option<String> {
+    if !self.open {
+      return None
+    }
+
+    let mut picked = None;
+    let mut enter = None;
+    let dir = &self.dir;
+    let all_files = &mut self.all_files;
+    let entries = self.entries
+      .get_or_insert_with(|| list_dir(dir, *all_files, &RomDb::load()));
+    let recent = self.recent.get_or_insert_with(recent_files);
+    let mut refresh = false;
+
+    ui.window(title)
+      .size([400.0, 400.0], imgui::Condition::FirstUseEver)
+      .opened(&mut self.open)
+      .build(|| {
+        if !recent.is_empty() {
+          ui.text(im_str!("recent:"));
+          for path in recent.iter() {
+            let name = Path::new(path).file_name()
+              .map_or(path.clone(), |n| n.to_string_lossy().into_owned());
+            if ui.selectable(&im_str!("{}##recent {}", name, path), false,
+                             ImGuiSelectableFlags::empty(), [0.0, 0.0]) {
+              picked = Some(path.clone());
+            }
+            if ui.is_item_hovered() {
+              ui.tooltip_text(path);
+            }
+          }
+          ui.separator();
+        }
+
+        ui.text(im_str!("{}", dir.display()));
+        if ui.small_button(im_str!("up")) {
+          enter = dir.parent().map(Path::to_path_buf);
+        }
+        ui.same_line(0.0);
+        refresh = ui.checkbox(im_str!("all files"), all_files);
+
+        ui.child_frame(im_str!("files"), [0.0, 0.0])
+          .show_borders(true)
+          .build(|| {
+            for e in entries.iter() {
+              let label = match (&e.title, e.is_dir) {
+                (_, true) => im_str!("{}/", e.name),
+                (Some(t), false) => im_str!("{}  ({})", e.name, t),
+                (None, false) => im_str!("{}", e.name),
+              };
+              if ui.selectable(&label, false, ImGuiSelectableFlags::empty(),
+                               [0.0, 0.0]) {
+                if e.is_dir {
+                  enter = Some(e.path.clone());
+                } else {
+                  picked = Some(e.path.to_string_lossy().into_owned());
+                }
+              }
+              if let (true, Some(d)) = (ui.is_item_hovered(), &e.description) {
+                ui.tooltip_text(d);
+              }
+            }
+          });
+      });
+
+    if let Some(dir) = enter {
+      self.dir = dir;
+      refresh = true;
+    }
+    if refresh {
+      self.entries = None;
+    }
+    if picked.is_some() {
+      self.recent = None;
+    }
+    picked
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keeps_recent_files_unique() {
+    let mut files = Vec::new();
+    for f in &["a.ch8", "b.ch8", "a.ch8"] {
+      push_recent(&mut files, f.to_string());
+    }
+    assert_eq!(files, vec!["a.ch8", "b.ch8"]);
+
+    for i in 0..20 {
+      push_recent(&mut files, format!("{}.ch8", i));
+    }
+    assert_eq!(files.len(), MAX_RECENT);
+    assert_eq!(files[0], "19.ch8");
+  }
+
+  #[test]
+  fn lists_directories_then_roms() {
+    let dir = std::env::temp_dir()
+      .join(format!("chipers-rombrowser-{}", std::process::id()));
+    fs::create_dir_all(dir.join("games")).unwrap();
+    for name in &["pong.ch8", "notes.txt", "TETRIS.C8", ".hidden.ch8"] {
+      fs::write(dir.join(name), [0x00, 0xe0]).unwrap();
+    }
+
+    let db = RomDb::default();
+    let names = |all| list_dir(&dir, all, &db).into_iter()
+      .map(|e| e.name).collect::<Vec<_>>();
+    assert_eq!(names(false), vec!["games", "TETRIS.C8", "pong.ch8"]);
+    assert_eq!(names(true), vec!["games", "TETRIS.C8", "notes.txt", "pong.ch8"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::config;
+
+// Entries shipped with the emulator
+const BUNDLED: &str = include_str!("romdb.toml");
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Settings for known ROMs, keyed by the SHA-1 of the ROM bytes:
+//
+//   [roms.<SHA-1 of the ROM>]
+//   title = "Some Game"
+//   description = "Move with 4 and 6, fire with 5"
+//   platform = "vip"
+//   display_wait = true
+//   palette = "amber"
+//   keys = { 5 = ["Space"] }
+//   buttons = { 5 = ["South", "East"] }
+//
+// `platform` is "chip8" or "vip", `cps` and `ipf` are the options of the same
+// name, and the quirks are `display_wait`, `clip` and `collision_rows`.  The
+// palette, layout and keys apply over palettes.toml and keymap.toml, but under
+// the entries they have for the ROM.  Gamepad buttons, named as in
+// gamepad.toml, replace the ones it binds to the same keys.
+//
+// romdb.toml in the configuration directory has the same format, and its
+// entries replace the bundled ones.
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+  Chip8,
+  Vip,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+  pub title: Option<String>,
+  pub description: Option<String>,
+  pub platform: Option<Platform>,
+  pub cps: Option<u64>,
+  pub ipf: Option<u32>,
+  #[serde(default)]
+  pub display_wait: bool,
+  #[serde(default)]
+  pub clip: bool,
+  #[serde(default)]
+  pub collision_rows: bool,
+  pub palette: Option<String>,
+  pub layout: Option<String>,
+  #[serde(default)]
+  pub keys: BTreeMap<String, Vec<String>>,
+  #[serde(default)]
+  pub buttons: BTreeMap<String, Vec<String>>,
+}
+
+impl RomInfo {
+  // Window title: the title of the ROM and its description
+  pub fn window_title(&self) -> String {
+    match (&self.title, &self.description) {
+      (Some(t), Some(d)) => format!("{} - {}", t, d),
+      (Some(t), None) => t.clone(),
+      _ => "Chipers".to_string(),
+    }
+  }
+}
+
+const ROMDB_FILE: &str = "romdb.toml";
+
+#[derive(Deserialize, Default)]
+pub struct RomDb {
+  #[serde(default)]
+  roms: BTreeMap<String, RomInfo>,
+}
+
+impl RomDb {
+  // The bundled entries, replaced by the ones of the user
+  pub fn load() -> Self {
+    let bundled: RomDb = toml::from_str(BUNDLED)
+      .expect("Invalid bundled ROM database");
+    bundled.merge(config::load(ROMDB_FILE))
+  }
+
+  fn merge(self, other: RomDb) -> Self {
+    RomDb {
+      roms: self.roms.into_iter()
+        .chain(other.roms)
+        .map(|(hash, info)| (hash.to_lowercase(), info))
+        .collect(),
+    }
+  }
+
+  // Settings of the ROM, if it is known
+  pub fn get(&self, rom: &[u8]) -> Option<&RomInfo> {
+    self.roms.get(&sha1(rom))
+  }
+}
+
+// Lowercase hexadecimal SHA-1
+pub fn sha1(rom: &[u8]) -> String {
+  sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bundled_database_parses() {
+    let db: RomDb = toml::from_str(BUNDLED).unwrap();
+    for hash in db.roms.keys() {
+      assert_eq!(hash.len(), 40, "{}", hash);
+      assert!(hash.chars().all(|c| c.is_ascii_hexdigit()), "{}", hash);
+    }
+  }
+
+  #[test]
+  fn hashes_roms() {
+    assert_eq!(sha1(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+  }
+
+  #[test]
+  fn user_entries_come_first() {
+    let user: RomDb = toml::from_str(r#"
+      [roms.A9993E364706816ABA3E25717850C26C9CD0D89D]
+      title = "Mine"
+      platform = "vip"
+    "#).unwrap();
+    let bundled: RomDb = toml::from_str(r#"
+      [roms.a9993e364706816aba3e25717850c26c9cd0d89d]
+      title = "Bundled"
+      description = "Original"
+      cps = 1000
+      clip = true
+      keys = { 5 = ["Space"] }
+
+      [roms.da39a3ee5e6b4b0d3255bfef95601890afd80709]
+      title = "Empty"
+      description = "Nothing to see"
+    "#).unwrap();
+    let db = bundled.merge(user);
+
+    let info = db.get(b"abc").unwrap();
+    assert_eq!(info.window_title(), "Mine");
+    assert_eq!(info.platform, Some(Platform::Vip));
+    assert_eq!(info.cps, None);
+
+    let info = db.get(b"").unwrap();
+    assert_eq!(info.window_title(), "Empty - Nothing to see");
+    assert_eq!(db.get(b"rom"), None);
+    assert_eq!(RomInfo::default().window_title(), "Chipers");
+
+    assert!(toml::from_str::<RomDb>("[roms.abc]\nplatform = \"schip\"").is_err());
+    assert!(toml::from_str::<RomDb>("[roms.abc]\nspeed = 10").is_err());
+  }
+}
@@ -0,0 +1,164 @@
+use time::{Duration, SteadyTime};
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Source of time of the scheduler
+
+pub trait Clock {
+  fn now(&self) -> SteadyTime;
+  fn sleep(&self, d: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> SteadyTime {
+    SteadyTime::now()
+  }
+
+  fn sleep(&self, d: Duration) {
+    std::thread::sleep(d.to_std().unwrap());
+  }
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Paces the main loop to a target repaint frequency
+
+pub struct FrameScheduler<C: Clock = SystemClock> {
+  clock: C,
+  interval: Duration,
+  frame_start: SteadyTime,
+  // Sleep granularity depends on platform.  We sleep until that much time is
+  // left, and busy wait for the rest.
+  sleep_slack: Duration,
+  // Number of frames that took longer than the interval
+  pub overtimes: u64,
+}
+
+impl FrameScheduler {
+  pub fn new(fps: usize) -> Self {
+    Self::with_clock(fps, SystemClock)
+  }
+}
+
+impl<C: Clock> FrameScheduler<C> {
+  pub fn with_clock(fps: usize, clock: C) -> Self {
+    Self {
+      interval: Duration::microseconds(1_000_000 / fps.max(1) as i64),
+      frame_start: clock.now(),
+      sleep_slack: Duration::microseconds(500),
+      overtimes: 0,
+      clock,
+    }
+  }
+
+  // Start a new frame, and return the time elapsed since the start of the
+  // previous one
+  pub fn start_frame(&mut self) -> Duration {
+    let now = self.clock.now();
+    let dt = now - self.frame_start;
+    self.frame_start = now;
+    dt
+  }
+
+  pub fn time_left(&self) -> Duration {
+    self.interval - (self.clock.now() - self.frame_start)
+  }
+
+  // Block until the current frame is over
+  pub fn wait(&mut self) {
+    let left = self.time_left();
+    if left < Duration::zero() {
+      self.overtimes += 1;
+      return
+    }
+
+    if left > self.sleep_slack {
+      self.clock.sleep(left - self.sleep_slack);
+    }
+
+    while self.time_left() > Duration::zero() {}
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use std::cell::Cell;
+  use std::rc::Rc;
+
+  // Clock moved by hand.  Every reading takes a microsecond, so that busy
+  // waits end.
+  #[derive(Clone)]
+  pub struct FakeClock {
+    start: SteadyTime,
+    elapsed: Rc<Cell<Duration>>,
+    pub slept: Rc<Cell<Duration>>,
+  }
+
+  impl FakeClock {
+    pub fn new() -> Self {
+      Self {
+        start: SteadyTime::now(),
+        elapsed: Rc::new(Cell::new(Duration::zero())),
+        slept: Rc::new(Cell::new(Duration::zero())),
+      }
+    }
+
+    pub fn advance(&self, d: Duration) {
+      self.elapsed.set(self.elapsed.get() + d);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+      self.elapsed.get()
+    }
+  }
+
+  impl Clock for FakeClock {
+    fn now(&self) -> SteadyTime {
+      self.advance(Duration::microseconds(1));
+      self.start + self.elapsed.get()
+    }
+
+    fn sleep(&self, d: Duration) {
+      self.slept.set(self.slept.get() + d);
+      self.advance(d);
+    }
+  }
+
+  #[test]
+  fn waits_until_the_end_of_the_frame() {
+    let clock = FakeClock::new();
+    let mut scheduler = FrameScheduler::with_clock(50, clock.clone());
+    scheduler.start_frame();
+    clock.advance(Duration::milliseconds(5));
+    scheduler.wait();
+
+    assert_eq!(scheduler.overtimes, 0);
+    // Sleeps for most of the 15ms left, and busy waits for the rest
+    assert!(clock.slept.get() > Duration::milliseconds(14));
+    assert!(clock.slept.get() < Duration::milliseconds(15));
+    let dt = scheduler.start_frame();
+    assert!(dt >= Duration::milliseconds(20));
+    assert!(dt < Duration::microseconds(20_010));
+  }
+
+  #[test]
+  fn counts_overtimes_without_waiting() {
+    let clock = FakeClock::new();
+    let mut scheduler = FrameScheduler::with_clock(50, clock.clone());
+    scheduler.start_frame();
+    clock.advance(Duration::milliseconds(30));
+    let before = clock.elapsed();
+    scheduler.wait();
+
+    assert_eq!(scheduler.overtimes, 1);
+    assert_eq!(clock.slept.get(), Duration::zero());
+    assert!(clock.elapsed() - before < Duration::microseconds(10));
+    // The next frame is given all the time that passed, to catch up
+    assert!(scheduler.start_frame() >= Duration::milliseconds(30));
+
+    clock.advance(Duration::milliseconds(10));
+    scheduler.wait();
+    assert_eq!(scheduler.overtimes, 1);
+  }
+}
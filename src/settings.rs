@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// config.toml in the configuration directory holds the defaults of options:
+//
+//   zoom = 8
+//   fps = 60
+//   cps = 1000
+//   shader = "crt-lottes"
+//   palette = "amber"
+//   layout = "qwerty"
+//
+// Options given on the command line win, then the settings of the ROM
+// database.  The palette and layout are replaced by the defaults of
+// palettes.toml and keymap.toml, if they have one.
+
+const SETTINGS_FILE: &str = "config.toml";
+
+// CPU frequency when neither the options nor the ROM database give a timing
+pub const DEFAULT_CPS: u64 = 600;
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+  pub zoom: usize,
+  pub fps: usize,
+  pub cps: Option<u64>,
+  pub shader: String,
+  pub palette: Option<String>,
+  pub layout: Option<String>,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      zoom: 10,
+      fps: 60,
+      cps: None,
+      shader: "crt-phosphor".to_string(),
+      palette: None,
+      layout: None,
+    }
+  }
+}
+
+impl Settings {
+  pub fn load() -> Self {
+    config::load(SETTINGS_FILE)
+  }
+
+  // The usage string with its "default: {zoom}", {fps}, {cps} and {shader}
+  // filled in.  Other braces are left alone.
+  pub fn usage(&self, usage: &str) -> String {
+    let defaults = [
+      ("zoom", self.zoom.to_string()),
+      ("fps", self.fps.to_string()),
+      ("cps", self.cps.unwrap_or(DEFAULT_CPS).to_string()),
+      ("shader", self.shader.clone()),
+    ];
+    defaults.iter().fold(usage.to_string(), |usage, (name, value)| {
+      usage.replace(&format!("default: {{{}}}", name),
+                    &format!("default: {}", value))
+    })
+  }
+}
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// State of the window at exit, in window.toml.  The size is restored until
+// another zoom is asked for.
+
+const WINDOW_FILE: &str = "window.toml";
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct WindowState {
+  pub zoom: usize,
+  pub size: Option<(f64, f64)>,
+}
+
+impl WindowState {
+  // Size to open the window with, at that zoom
+  pub fn load(zoom: usize) -> Option<(f64, f64)> {
+    let state: WindowState = config::load(WINDOW_FILE);
+    state.size.filter(|_| state.zoom == zoom)
+  }
+
+  pub fn save(&self) {
+    if let Err(e) = config::save(WINDOW_FILE, self) {
+      eprintln!("Error saving window state: {}", e);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fills_usage_defaults() {
+    let settings: Settings = toml::from_str(r#"
+      zoom = 8
+      shader = "plain"
+    "#).unwrap();
+    assert_eq!(settings.fps, 60);
+    assert_eq!(settings.usage("-z [default: {zoom}] --fps [default: {fps}] \
+                               --shader [default: {shader}] {width} {fps}"),
+               "-z [default: 8] --fps [default: 60] \
+                --shader [default: plain] {width} {fps}");
+    assert_eq!(settings.usage("(default: {cps})"), "(default: 600)");
+    let settings = Settings { cps: Some(1000), ..Settings::default() };
+    assert_eq!(settings.usage("(default: {cps})"), "(default: 1000)");
+
+    assert!(toml::from_str::<Settings>("zom = 8").is_err());
+  }
+}
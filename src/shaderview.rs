@@ -0,0 +1,42 @@
+use imgui::{Ui, ImStr, im_str};
+
+use crate::glscreen::chain::Chain;
+
+pub struct ShaderView {
+  open: bool,
+}
+
+impl ShaderView {
+  pub fn new() -> ShaderView {
+    ShaderView {
+      open: true,
+    }
+  }
+
+  pub fn draw(&mut self, ui: &Ui, title: &ImStr, chain: &mut Chain) {
+    ui.window(title)
+      .opened(&mut self.open)
+      .build(|| {
+        ui.text(im_str!("{}", chain.name()));
+
+        for (i, pass) in chain.passes.iter_mut().enumerate() {
+          ui.separator();
+          ui.text(im_str!("pass {}: {}", i, pass.name()));
+
+          // Labels must be unique across passes
+          for p in &mut pass.parameters {
+            ui.slider_float(&im_str!("{}##{}", p.name, i), &mut p.value,
+                            p.min, p.max)
+              .build();
+          }
+
+          if !pass.parameters.is_empty()
+            && ui.small_button(&im_str!("defaults##{}", i)) {
+            for p in &mut pass.parameters {
+              p.value = p.default;
+            }
+          }
+        }
+      });
+  }
+}
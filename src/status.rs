@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+// How long a message stays on screen
+const SHOW_TIME: Duration = Duration::from_secs(3);
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Messages for the user while the emulator runs, like where a screenshot was
+// saved.  Each front-end shows them its own way: printing them to stderr would
+// garble the terminal one.
+
+thread_local! {
+  static PENDING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn post<S: Into<String>>(message: S) {
+  PENDING.with(|p| p.borrow_mut().push(message.into()));
+}
+
+// Messages posted since the last call
+pub fn take() -> Vec<String> {
+  PENDING.with(|p| p.borrow_mut().drain(..).collect())
+}
+
+// Print messages nobody has shown, once the front-end is gone
+pub fn flush() {
+  for m in take() {
+    eprintln!("{}", m);
+  }
+}
+
+// Latest message, for front-ends with room for a single line
+pub struct StatusLine {
+  message: Option<(String, Instant)>,
+}
+
+impl StatusLine {
+  pub fn new() -> Self {
+    Self {
+      message: None,
+    }
+  }
+
+  // Message to show on this frame, if any
+  pub fn current(&mut self) -> Option<&str> {
+    if let Some(m) = take().pop() {
+      self.message = Some((m, Instant::now()));
+    }
+    if let Some((_, t)) = self.message {
+      if t.elapsed() > SHOW_TIME {
+        self.message = None;
+      }
+    }
+    self.message.as_ref().map(|(m, _)| m.as_str())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shows_latest_message() {
+    let mut line = StatusLine::new();
+    assert_eq!(line.current(), None);
+
+    post("first");
+    post("second");
+    assert_eq!(line.current(), Some("second"));
+    assert_eq!(line.current(), Some("second"));
+    assert!(take().is_empty());
+
+    line.message = Some(("old".to_string(), Instant::now() - SHOW_TIME * 2));
+    assert_eq!(line.current(), None);
+  }
+}
@@ -0,0 +1,151 @@
+use crate::chip8::screen::{PixelScreen, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::palette::Palette;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Renders the logical screen to an RGBA framebuffer, without the GPU
+
+pub struct Rasterizer {
+  pub zoom: usize,
+  pub palette: Palette,
+  // Darken the borders of each pixel
+  pub grid: bool,
+  // Brightness kept by a pixel on each frame after it is turned off, between
+  // 0 (no persistence) and 1
+  pub persistence: f32,
+  intensities: Vec<f32>,
+  // Last value of each pixel, to fade out with the right color
+  last_pixels: Vec<u8>,
+  buffer: Vec<u8>,
+}
+
+impl Rasterizer {
+  pub fn new(zoom: usize) -> Self {
+    let zoom = zoom.max(1);
+
+    Self {
+      zoom,
+      palette: Palette::default(),
+      grid: false,
+      persistence: 0.0,
+      intensities: vec![0.0; SCREEN_WIDTH * SCREEN_HEIGHT],
+      last_pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+      buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * zoom * zoom * 4],
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    SCREEN_WIDTH * self.zoom
+  }
+
+  pub fn height(&self) -> usize {
+    SCREEN_HEIGHT * self.zoom
+  }
+
+  pub fn render(&mut self, screen: &PixelScreen) -> &[u8] {
+    let zoom = self.zoom;
+    let width = self.width();
+    let bg = self.palette.background();
+
+    for (i, &p) in screen.pixels().iter().enumerate() {
+      let intensity = if p != 0 { 1.0 }
+                      else { self.intensities[i] * self.persistence };
+      self.intensities[i] = intensity;
+      if p != 0 {
+        self.last_pixels[i] = p;
+      }
+      let fg = self.palette.color(self.last_pixels[i]);
+
+      let mut color = [0u8; 3];
+      for (c, (&b, &f)) in color.iter_mut().zip(bg.iter().zip(fg.iter())) {
+        *c = (b as f32 + (f as f32 - b as f32) * intensity) as u8;
+      }
+      let border_color = color.map(|c| c / 2);
+
+      let x0 = (i % SCREEN_WIDTH) * zoom;
+      let y0 = (i / SCREEN_WIDTH) * zoom;
+      for y in 0..zoom {
+        for x in 0..zoom {
+          let border = self.grid && zoom > 2 && (x == zoom - 1 || y == zoom - 1);
+          let pos = ((y0 + y) * width + x0 + x) * 4;
+          self.buffer[pos..(pos + 3)]
+            .copy_from_slice(if border { &border_color } else { &color });
+          self.buffer[pos + 3] = 0xff;
+        }
+      }
+    }
+
+    &self.buffer
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rasterizer(zoom: usize) -> Rasterizer {
+    let mut r = Rasterizer::new(zoom);
+    r.palette = Palette {
+      colors: [[0, 0, 0], [200, 100, 40], [0, 0, 0], [0, 0, 0]],
+    };
+    r
+  }
+
+  fn rgb(buffer: &[u8], r: &Rasterizer, x: usize, y: usize) -> [u8; 3] {
+    let pos = (y * r.width() + x) * 4;
+    [buffer[pos], buffer[pos + 1], buffer[pos + 2]]
+  }
+
+  #[test]
+  fn zooms_pixels() {
+    let mut r = rasterizer(3);
+    let mut screen = PixelScreen::new();
+    screen.draw_pixel(1, 1, 0);
+
+    assert_eq!((r.width(), r.height()), (192, 96));
+    let buffer = r.render(&screen).to_vec();
+    assert_eq!(buffer.len(), 192 * 96 * 4);
+    assert_eq!(rgb(&buffer, &r, 2, 0), [0, 0, 0]);
+    assert_eq!(rgb(&buffer, &r, 3, 0), [200, 100, 40]);
+    assert_eq!(rgb(&buffer, &r, 5, 2), [200, 100, 40]);
+    assert_eq!(rgb(&buffer, &r, 6, 0), [0, 0, 0]);
+    assert_eq!(buffer[3], 0xff);
+  }
+
+  #[test]
+  fn darkens_grid_lines() {
+    let mut r = rasterizer(4);
+    r.grid = true;
+    let mut screen = PixelScreen::new();
+    screen.draw_pixel(1, 0, 0);
+
+    let buffer = r.render(&screen).to_vec();
+    assert_eq!(rgb(&buffer, &r, 2, 2), [200, 100, 40]);
+    assert_eq!(rgb(&buffer, &r, 3, 0), [100, 50, 20]);
+    assert_eq!(rgb(&buffer, &r, 0, 3), [100, 50, 20]);
+
+    // Too small to leave room for a grid
+    let mut r = rasterizer(2);
+    r.grid = true;
+    let buffer = r.render(&screen).to_vec();
+    assert_eq!(rgb(&buffer, &r, 1, 1), [200, 100, 40]);
+  }
+
+  #[test]
+  fn fades_out_pixels() {
+    let mut r = rasterizer(1);
+    r.persistence = 0.5;
+    let mut screen = PixelScreen::new();
+    screen.draw_pixel(1, 0, 0);
+    r.render(&screen);
+
+    screen.draw_pixel(1, 0, 0);
+    let buffer = r.render(&screen).to_vec();
+    assert_eq!(rgb(&buffer, &r, 0, 0), [100, 50, 20]);
+    let buffer = r.render(&screen).to_vec();
+    assert_eq!(rgb(&buffer, &r, 0, 0), [50, 25, 10]);
+
+    r.persistence = 0.0;
+    let buffer = r.render(&screen).to_vec();
+    assert_eq!(rgb(&buffer, &r, 0, 0), [0, 0, 0]);
+  }
+}
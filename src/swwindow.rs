@@ -0,0 +1,108 @@
+use minifb::{Key, KeyRepeat, ScaleMode, Window, WindowOptions};
+use time::{Duration, SteadyTime};
+
+use crate::capture::Capture;
+use crate::chip8::keyboard::SimpleKeyboard;
+use crate::chip8::screen::PixelScreen;
+use crate::scheduler::FrameScheduler;
+use crate::settings::WindowState;
+use crate::status::{self, StatusLine};
+use crate::swscreen::Rasterizer;
+use crate::chip8::{PERIOD_60HZ, Screen};
+use crate::{Args, Machine, emulate, gamepads, keymap, palette, reset_rom, speed};
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Front-end using the software rasterizer in a minimal window
+
+pub fn run(args: &Args, chip8: &mut Machine) {
+  let mut rasterizer = Rasterizer::new(args.flag_zoom);
+  rasterizer.grid = args.flag_grid;
+  rasterizer.persistence = args.flag_persistence;
+  rasterizer.palette = palette(args).unwrap_or_default();
+
+  let width = rasterizer.width();
+  let height = rasterizer.height();
+  // The framebuffer is stretched to the size the window had last time
+  let size = WindowState::load(args.flag_zoom)
+    .map_or((width, height), |(w, h)| (w as usize, h as usize));
+  let title = args.rom_info.window_title();
+  let mut window = Window::new(&title, size.0, size.1, WindowOptions {
+    resize: true,
+    scale_mode: ScaleMode::Stretch,
+    ..WindowOptions::default()
+  }).expect("Error opening window");
+  let mut framebuffer = vec![0u32; width * height];
+
+  let mut screen = PixelScreen::new();
+  let mut keyboard = SimpleKeyboard::new();
+  let keymap = keymap(args);
+  let mut gamepads = gamepads(args);
+  let mut scheduler = FrameScheduler::new(args.flag_fps);
+  let mut render_dt = Duration::zero();
+  let mut capture = Capture::new(args, rasterizer.palette);
+  let mut paused = false;
+  // Messages go to the title bar
+  let mut status = StatusLine::new();
+  let mut shown = None;
+
+  while window.is_open() && !window.is_key_down(Key::Escape) {
+    for k in window.get_keys_pressed(KeyRepeat::No) {
+      match k {
+        Key::F3 => match reset_rom(args, chip8) {
+          Ok(()) => screen.clear(),
+          Err(e) => status::post(
+            format!("Error loading {}: {}", args.arg_rom, e)),
+        },
+        Key::F5 => paused = !paused,
+        Key::F6 => {
+          paused = true;
+          chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+        },
+        Key::F11 => capture.toggle_recording(),
+        Key::F12 => capture.screenshot(&screen),
+        _ => if let Some(c) = keymap.key(&format!("{:?}", k)) {
+          keyboard.press_key(c);
+        },
+      }
+    }
+    for k in window.get_keys_released() {
+      if let Some(c) = keymap.key(&format!("{:?}", k)) {
+        keyboard.release_key(c);
+      }
+    }
+
+    gamepads.poll(&mut keyboard);
+
+    let fast_forward = window.is_key_down(Key::Tab);
+    emulate(args, chip8, &mut scheduler, speed(args, paused, fast_forward),
+            render_dt, &mut screen, &mut keyboard);
+    capture.frame(&screen);
+
+    let before_render = SteadyTime::now();
+    let rgba = rasterizer.render(&screen);
+    for (p, c) in framebuffer.iter_mut().zip(rgba.chunks(4)) {
+      *p = ((c[0] as u32) << 16) | ((c[1] as u32) << 8) | (c[2] as u32);
+    }
+    let message = status.current().map(str::to_string);
+    if message != shown {
+      match message {
+        Some(ref m) => window.set_title(&format!("{} - {}", title, m)),
+        None => window.set_title(&title),
+      }
+      shown = message;
+    }
+    window.update_with_buffer(&framebuffer, width, height)
+      .expect("Error updating window");
+    render_dt = SteadyTime::now() - before_render;
+
+    scheduler.wait();
+  }
+
+  capture.finish(&screen);
+
+  let (w, h) = window.get_size();
+  WindowState {
+    zoom: args.flag_zoom,
+    size: Some((w as f64, h as f64)),
+  }.save();
+}
@@ -0,0 +1,243 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crossterm::{cursor, event, execute, queue, style, terminal};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers,
+                       KeyboardEnhancementFlags};
+use crossterm::style::Color;
+use time::{Duration, SteadyTime};
+
+use crate::capture::Capture;
+use crate::chip8::disasm::disassemble;
+use crate::chip8::keyboard::SimpleKeyboard;
+use crate::chip8::screen::{PixelScreen, SCREEN_WIDTH, SCREEN_HEIGHT};
+use crate::palette::Palette;
+use crate::scheduler::FrameScheduler;
+use crate::status::{self, StatusLine};
+use crate::keymap::char_name;
+use crate::chip8::{PERIOD_60HZ, Screen};
+use crate::{Args, Machine, emulate, gamepads, keymap, palette, reset_rom, speed};
+
+// Most terminals do not report key releases, so keys are released after that
+// long without a repeat.  The first repeat comes later than the following ones,
+// usually after 500 to 660ms.
+const KEY_FIRST_REPEAT_MS: u128 = 700;
+const KEY_REPEAT_MS: u128 = 100;
+const PANEL_COLUMN: u16 = SCREEN_WIDTH as u16 + 2;
+const DISASM_ROWS: usize = 12;
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Front-end drawing in the terminal, two pixels per character cell
+
+// Restores the terminal even if the emulator panics
+struct RawTerminal {
+  // Whether the terminal reports key releases
+  reports_releases: bool,
+}
+
+impl RawTerminal {
+  fn new(title: &str) -> io::Result<Self> {
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide,
+             terminal::Clear(terminal::ClearType::All),
+             terminal::SetTitle(title))?;
+    let reports_releases = terminal::supports_keyboard_enhancement()
+      .unwrap_or(false);
+    if reports_releases {
+      execute!(io::stdout(), event::PushKeyboardEnhancementFlags(
+        KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+    }
+    Ok(Self { reports_releases })
+  }
+}
+
+impl Drop for RawTerminal {
+  fn drop(&mut self) {
+    if self.reports_releases {
+      let _ = execute!(io::stdout(), event::PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+  }
+}
+
+pub fn run(args: &Args, chip8: &mut Machine) {
+  let terminal = RawTerminal::new(&args.rom_info.window_title())
+    .expect("Error setting up terminal");
+  let mut out = io::BufWriter::new(io::stdout());
+
+  let palette = palette(args).unwrap_or_default();
+  let mut screen = PixelScreen::new();
+  let mut keyboard = SimpleKeyboard::new();
+  let keymap = keymap(args);
+  let mut gamepads = gamepads(args);
+  let mut scheduler = FrameScheduler::new(args.flag_fps);
+  let mut render_dt = Duration::zero();
+  let mut capture = Capture::new(args, palette);
+  let mut paused = false;
+  let mut status = StatusLine::new();
+
+  // Time of the last press of each key, and whether it was a repeat
+  let mut last_presses: [Option<(Instant, bool)>; 16] = [None; 16];
+  let mut fast_forward_press: Option<(Instant, bool)> = None;
+
+  'running: loop {
+    while event::poll(std::time::Duration::from_secs(0)).unwrap_or(false) {
+      if let Ok(Event::Key(k)) = event::read() {
+        if k.code == KeyCode::Esc
+          || (k.code == KeyCode::Char('c')
+              && k.modifiers.contains(KeyModifiers::CONTROL)) {
+          break 'running;
+        }
+
+        let released = k.kind == KeyEventKind::Release;
+        if k.kind == KeyEventKind::Press {
+          match k.code {
+            KeyCode::F(3) => match reset_rom(args, chip8) {
+              Ok(()) => screen.clear(),
+              Err(e) => status::post(
+                format!("Error loading {}: {}", args.arg_rom, e)),
+            },
+            KeyCode::F(5) => paused = !paused,
+            KeyCode::F(6) => {
+              paused = true;
+              chip8.run(PERIOD_60HZ, &mut screen, &mut keyboard);
+            },
+            KeyCode::F(11) => capture.toggle_recording(),
+            KeyCode::F(12) => capture.screenshot(&screen),
+            _ => (),
+          }
+        }
+        if k.code == KeyCode::Tab {
+          fast_forward_press = press(fast_forward_press, released);
+        }
+        let name = match k.code {
+          KeyCode::Char(c) => char_name(c),
+          code => format!("{:?}", code),
+        };
+        if let Some(c) = keymap.key(&name) {
+          last_presses[c as usize] = press(last_presses[c as usize], released);
+          if released {
+            keyboard.release_key(c);
+          } else {
+            keyboard.press_key(c);
+          }
+        }
+      }
+    }
+
+    if !terminal.reports_releases {
+      for (c, p) in last_presses.iter_mut().enumerate() {
+        if timed_out(*p) {
+          keyboard.release_key(c as u8);
+          *p = None;
+        }
+      }
+      if timed_out(fast_forward_press) {
+        fast_forward_press = None;
+      }
+    }
+    gamepads.poll(&mut keyboard);
+
+    emulate(args, chip8, &mut scheduler,
+            speed(args, paused, fast_forward_press.is_some()),
+            render_dt, &mut screen, &mut keyboard);
+    capture.frame(&screen);
+
+    let before_render = SteadyTime::now();
+    draw(&mut out, &screen, chip8, &palette, paused, status.current())
+      .expect("Error drawing to terminal");
+    render_dt = SteadyTime::now() - before_render;
+
+    scheduler.wait();
+  }
+
+  capture.finish(&screen);
+}
+
+fn press(last: Option<(Instant, bool)>,
+         released: bool) -> Option<(Instant, bool)> {
+  if released { None }
+  else { Some((Instant::now(), last.is_some())) }
+}
+
+fn timed_out(last: Option<(Instant, bool)>) -> bool {
+  match last {
+    Some((t, repeated)) => {
+      let timeout = if repeated { KEY_REPEAT_MS } else { KEY_FIRST_REPEAT_MS };
+      t.elapsed().as_millis() > timeout
+    },
+    None => false,
+  }
+}
+
+fn draw<W: Write>(out: &mut W, screen: &PixelScreen, chip8: &Machine,
+                  palette: &Palette, paused: bool,
+                  message: Option<&str>) -> io::Result<()> {
+  let color = |p: u8| {
+    let c = palette.color(p);
+    Color::Rgb { r: c[0], g: c[1], b: c[2] }
+  };
+
+  // The upper half block shows the top pixel in the foreground color, and the
+  // bottom pixel in the background color.  Colors are only sent when they
+  // change.
+  let pixels = screen.pixels();
+  let mut current = None;
+  for row in 0..(SCREEN_HEIGHT / 2) {
+    queue!(out, cursor::MoveTo(0, row as u16))?;
+    for x in 0..SCREEN_WIDTH {
+      let top = pixels[(row * 2) * SCREEN_WIDTH + x];
+      let bottom = pixels[(row * 2 + 1) * SCREEN_WIDTH + x];
+      if current != Some((top, bottom)) {
+        queue!(out,
+               style::SetForegroundColor(color(top)),
+               style::SetBackgroundColor(color(bottom)))?;
+        current = Some((top, bottom));
+      }
+      queue!(out, style::Print('▀'))?;
+    }
+  }
+  queue!(out, style::ResetColor)?;
+
+  // Status line under the screen
+  queue!(out,
+         cursor::MoveTo(0, (SCREEN_HEIGHT / 2) as u16),
+         style::Print(message.unwrap_or("")),
+         terminal::Clear(terminal::ClearType::UntilNewLine))?;
+
+  // Side panel
+  let cpu = &chip8.cpu;
+  let mut lines = vec![
+    format!("pc: {:03x}  i: {:03x}", cpu.pc, cpu.i),
+    format!("delay: {:02x}  sound: {:02x}", cpu.delay_timer, cpu.sound_timer),
+  ];
+  for r in (0..cpu.v.len()).step_by(4) {
+    lines.push(format!("v{:x}: {:02x}  v{:x}: {:02x}  v{:x}: {:02x}  v{:x}: {:02x}",
+                       r, cpu.v[r], r + 1, cpu.v[r + 1],
+                       r + 2, cpu.v[r + 2], r + 3, cpu.v[r + 3]));
+  }
+  lines.push(if paused { "paused (F5 resumes, F6 advances)".to_string() }
+             else { String::new() });
+
+  let mem = chip8.ram.read_all();
+  let start = cpu.pc - (cpu.pc.min(DISASM_ROWS) & !1);
+  for a in (start..(start + DISASM_ROWS * 2)).step_by(2) {
+    if a + 1 >= mem.len() {
+      break;
+    }
+    let opcode = ((mem[a] as u16) << 8) | (mem[a + 1] as u16);
+    let marker = if a == cpu.pc { ">" } else { " " };
+    lines.push(format!("{} {:03x}: {:04x} {}", marker, a, opcode,
+                       disassemble(opcode)));
+  }
+
+  for (row, line) in lines.iter().enumerate() {
+    queue!(out,
+           cursor::MoveTo(PANEL_COLUMN, row as u16),
+           style::Print(line),
+           terminal::Clear(terminal::ClearType::UntilNewLine))?;
+  }
+
+  out.flush()
+}
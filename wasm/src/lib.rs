@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+
+use chipers_core::{Chip8, PERIOD_60HZ, ROM_START, Timing};
+use chipers_core::cpu::Cpu;
+use chipers_core::keyboard::SimpleKeyboard;
+use chipers_core::memory::{RAM, RAM_LENGTH};
+use chipers_core::screen::{PixelScreen, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// WebAssembly build of the emulator, driven from JavaScript through the
+// functions below.  www/chipers.js wraps them, and www/index.html runs ROMs in
+// a canvas.  To build it:
+//
+//   cargo build -p chipers-wasm --release --target wasm32-unknown-unknown
+//   cp target/wasm32-unknown-unknown/release/chipers_wasm.wasm wasm/www/
+//
+// then serve wasm/www over HTTP.  `node wasm/test.mjs` runs the module
+// without a browser.
+//
+// To load a ROM, JavaScript copies it to the buffer returned by rom_buffer,
+// then calls load_rom.  The framebuffer holds one byte per pixel, 0 when off,
+// or the planes the pixel is lit in.
+
+struct Emulator {
+  chip8: Chip8<Cpu, RAM>,
+  screen: PixelScreen,
+  keyboard: SimpleKeyboard,
+  rom: Vec<u8>,
+}
+
+impl Emulator {
+  fn new(seed: u64) -> Self {
+    Self {
+      chip8: Chip8::new(Cpu::with_seed(seed), RAM::new()),
+      screen: PixelScreen::new(),
+      keyboard: SimpleKeyboard::new(),
+      rom: Vec::new(),
+    }
+  }
+}
+
+thread_local! {
+  // Boxed so that the framebuffer does not move between calls
+  static EMULATOR: RefCell<Box<Emulator>> =
+    RefCell::new(Box::new(Emulator::new(0)));
+}
+
+fn with<T>(f: impl FnOnce(&mut Emulator) -> T) -> T {
+  EMULATOR.with(|e| f(&mut e.borrow_mut()))
+}
+
+// Start over, with random numbers from `seed`
+#[no_mangle]
+pub extern "C" fn init(seed: u32) {
+  with(|e| *e = Emulator::new(seed as u64));
+}
+
+// Buffer of `len` bytes to copy a ROM into
+#[no_mangle]
+pub extern "C" fn rom_buffer(len: usize) -> *mut u8 {
+  with(|e| {
+    e.rom.resize(len, 0);
+    e.rom.as_mut_ptr()
+  })
+}
+
+// Reset the machine with the ROM of the buffer.  Returns 0 if it does not
+// fit in memory.
+#[no_mangle]
+pub extern "C" fn load_rom() -> u32 {
+  with(|e| {
+    if e.rom.len() > RAM_LENGTH - ROM_START {
+      return 0
+    }
+    e.chip8.reset();
+    e.chip8.load_rom(&e.rom);
+    e.screen = PixelScreen::new();
+    e.keyboard = SimpleKeyboard::new();
+    1
+  })
+}
+
+// Emulate one 60Hz frame
+#[no_mangle]
+pub extern "C" fn run_frame() {
+  with(|e| e.chip8.run(PERIOD_60HZ, &mut e.screen, &mut e.keyboard));
+}
+
+#[no_mangle]
+pub extern "C" fn framebuffer() -> *const u8 {
+  with(|e| e.screen.pixels().as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn screen_width() -> u32 {
+  SCREEN_WIDTH as u32
+}
+
+#[no_mangle]
+pub extern "C" fn screen_height() -> u32 {
+  SCREEN_HEIGHT as u32
+}
+
+#[no_mangle]
+pub extern "C" fn set_key(key: u32, pressed: u32) {
+  if key > 0xF {
+    return
+  }
+  with(|e| if pressed != 0 {
+    e.keyboard.press_key(key as u8)
+  } else {
+    e.keyboard.release_key(key as u8)
+  });
+}
+
+// Execute `hz` instructions per second
+#[no_mangle]
+pub extern "C" fn set_cps(hz: u32) {
+  with(|e| {
+    e.chip8.freq = hz as u64;
+    e.chip8.timing = Timing::RealTime;
+  });
+}
+
+// Execute exactly `n` instructions per frame, as Octo does
+#[no_mangle]
+pub extern "C" fn set_ipf(n: u32) {
+  with(|e| e.chip8.timing = Timing::PerFrame(n));
+}
+
+// Whether the buzzer sounds
+#[no_mangle]
+pub extern "C" fn sound() -> u32 {
+  with(|e| (e.chip8.cpu.sound_timer > 0) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Draw the 0 glyph once key 5 is held, then loop
+  const DRAW_ON_KEY: [u8; 14] = [
+    0x60, 0x05, // LD V0, 5
+    0xE0, 0x9E, // SKP V0
+    0x12, 0x02, // JP 202
+    0xA0, 0x00, // LD I, 0
+    0x61, 0x00, // LD V1, 0
+    0xD1, 0x15, // DRW V1, V1, 5
+    0x12, 0x0C, // JP 20C
+  ];
+
+  fn load(rom: &[u8]) -> u32 {
+    let buf = rom_buffer(rom.len());
+    unsafe { std::ptr::copy_nonoverlapping(rom.as_ptr(), buf, rom.len()) };
+    load_rom()
+  }
+
+  fn pixel(x: usize, y: usize) -> u8 {
+    let pixels = framebuffer();
+    unsafe { *pixels.add(y * screen_width() as usize + x) }
+  }
+
+  #[test]
+  fn runs_frames() {
+    init(1);
+    assert_eq!(load(&DRAW_ON_KEY), 1);
+    run_frame();
+    assert_eq!(pixel(0, 0), 0);
+
+    set_key(5, 1);
+    run_frame();
+    assert_eq!(pixel(0, 0), 1);
+    assert_eq!(pixel(0, 1), 1);
+    assert_eq!(pixel(1, 1), 0);
+
+    // The screen is cleared by loading
+    assert_eq!(load(&DRAW_ON_KEY), 1);
+    assert_eq!(pixel(0, 0), 0);
+    assert_eq!(load(&[0; RAM_LENGTH]), 0);
+  }
+}
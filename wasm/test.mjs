@@ -0,0 +1,51 @@
+// Run the WebAssembly build without a browser:
+//
+//   cargo build -p chipers-wasm --release --target wasm32-unknown-unknown
+//   node wasm/test.mjs [path to chipers_wasm.wasm]
+
+import assert from 'node:assert/strict';
+import { readFileSync } from 'node:fs';
+import { Chipers } from './www/chipers.js';
+
+const path = process.argv[2] ?? new URL(
+  '../target/wasm32-unknown-unknown/release/chipers_wasm.wasm', import.meta.url);
+const chipers = await Chipers.load(readFileSync(path));
+
+// Draw the 0 glyph once key 5 is held, then loop
+const DRAW_ON_KEY = new Uint8Array([
+  0x60, 0x05, // LD V0, 5
+  0xE0, 0x9E, // SKP V0
+  0x12, 0x02, // JP 202
+  0xA0, 0x00, // LD I, 0
+  0x61, 0x00, // LD V1, 0
+  0xD1, 0x15, // DRW V1, V1, 5
+  0x12, 0x0C, // JP 20C
+]);
+
+assert.equal(chipers.width, 64);
+assert.equal(chipers.height, 32);
+
+chipers.loadRom(DRAW_ON_KEY);
+chipers.runFrame();
+assert.equal(chipers.framebuffer()[0], 0);
+
+chipers.setKey(5, true);
+chipers.runFrame();
+const pixels = chipers.framebuffer();
+// Top row of the 0 glyph is 0xF0
+assert.deepEqual([...pixels.slice(0, 5)], [1, 1, 1, 1, 0]);
+assert.equal(chipers.sound, false);
+
+// Keys are released by loading.  At 6 instructions per frame, the sprite is
+// drawn in the frame following the key press.
+chipers.loadRom(DRAW_ON_KEY);
+chipers.setIpf(6);
+chipers.runFrame();
+assert.equal(chipers.framebuffer()[0], 0);
+chipers.setKey(5, true);
+chipers.runFrame();
+assert.equal(chipers.framebuffer()[0], 1);
+
+assert.throws(() => chipers.loadRom(new Uint8Array(0x1000)), /too large/);
+
+console.log('ok');
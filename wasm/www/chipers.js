@@ -0,0 +1,60 @@
+// JavaScript API over the exports of chipers_wasm.wasm.  Works in browsers and
+// in Node.
+
+export class Chipers {
+  // Instantiate the module from its bytes, or fetch it from a URL
+  static async load(source) {
+    const bytes = (source instanceof ArrayBuffer || ArrayBuffer.isView(source))
+      ? source
+      : await (await fetch(source)).arrayBuffer();
+    const { instance } = await WebAssembly.instantiate(bytes, {});
+    return new Chipers(instance.exports);
+  }
+
+  constructor(exports) {
+    this.wasm = exports;
+    this.width = exports.screen_width();
+    this.height = exports.screen_height();
+    exports.init(Math.floor(Math.random() * 0x100000000));
+  }
+
+  // Reset the machine with a ROM, given as a Uint8Array
+  loadRom(rom) {
+    const ptr = this.wasm.rom_buffer(rom.length);
+    new Uint8Array(this.wasm.memory.buffer, ptr, rom.length).set(rom);
+    if (!this.wasm.load_rom()) {
+      throw new Error(`ROM too large: ${rom.length} bytes`);
+    }
+  }
+
+  // Emulate one 60Hz frame
+  runFrame() {
+    this.wasm.run_frame();
+  }
+
+  // One byte per pixel, row by row: 0 when off, or the planes it is lit in.
+  // The view is only valid until the next call.
+  framebuffer() {
+    return new Uint8Array(this.wasm.memory.buffer, this.wasm.framebuffer(),
+                          this.width * this.height);
+  }
+
+  // Press or release key 0 to F
+  setKey(key, pressed) {
+    this.wasm.set_key(key, pressed ? 1 : 0);
+  }
+
+  // Execute `hz` instructions per second
+  setCps(hz) {
+    this.wasm.set_cps(hz);
+  }
+
+  // Execute exactly `n` instructions per frame
+  setIpf(n) {
+    this.wasm.set_ipf(n);
+  }
+
+  get sound() {
+    return this.wasm.sound() !== 0;
+  }
+}